@@ -1,4 +1,10 @@
-use crate::{LvmProviders, ProviderConfiguration, TextToImageRequest};
+#[cfg(feature = "openai-compatible")]
+use crate::OpenAiCompatibleConfiguration;
+#[cfg(feature = "replicate")]
+use crate::ReplicateConfiguration;
+use crate::{
+    Automatic1111PoolConfiguration, LvmProviders, ProviderConfiguration, TextToImageRequest,
+};
 use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +15,10 @@ pub struct Cli {
     #[clap(long, default_value_t = CliLvmProviders::OpenAi)]
     pub provider: CliLvmProviders,
 
+    /// The name of a provider registered by a downstream crate, required with `--provider custom`
+    #[clap(long, required_if_eq("provider", "custom"))]
+    pub custom_provider: Option<String>,
+
     // Configuration for the provider
     #[clap(flatten)]
     pub provider_configuration: ProviderConfiguration,
@@ -23,7 +33,28 @@ pub enum CliLvmProviders {
     #[default]
     OpenAi,
     Automatic1111,
+    /// Several Automatic1111 instances, set with a comma-separated `--base-url`.
+    Automatic1111Pool,
     XAi,
+    #[cfg(feature = "stability")]
+    Stability,
+    #[cfg(feature = "comfyui")]
+    ComfyUi,
+    #[cfg(feature = "invokeai")]
+    InvokeAi,
+    #[cfg(feature = "huggingface")]
+    HuggingFace,
+    /// Imagen on Vertex AI, set with `--base-url`.
+    #[cfg(feature = "imagen")]
+    Imagen,
+    /// A model hosted on Replicate, set with `--model`.
+    #[cfg(feature = "replicate")]
+    Replicate,
+    /// Any endpoint implementing OpenAI's images API, set with `--base-url`.
+    #[cfg(feature = "openai-compatible")]
+    OpenAiCompatible,
+    /// A provider registered by a downstream crate, set with `--custom-provider`.
+    Custom,
 }
 
 impl std::fmt::Display for CliLvmProviders {
@@ -31,7 +62,23 @@ impl std::fmt::Display for CliLvmProviders {
         match self {
             CliLvmProviders::OpenAi => write!(f, "open-ai"),
             CliLvmProviders::Automatic1111 => write!(f, "automatic1111"),
+            CliLvmProviders::Automatic1111Pool => write!(f, "automatic1111-pool"),
             CliLvmProviders::XAi => write!(f, "x-ai"),
+            #[cfg(feature = "stability")]
+            CliLvmProviders::Stability => write!(f, "stability"),
+            #[cfg(feature = "comfyui")]
+            CliLvmProviders::ComfyUi => write!(f, "comfy-ui"),
+            #[cfg(feature = "invokeai")]
+            CliLvmProviders::InvokeAi => write!(f, "invoke-ai"),
+            #[cfg(feature = "huggingface")]
+            CliLvmProviders::HuggingFace => write!(f, "hugging-face"),
+            #[cfg(feature = "imagen")]
+            CliLvmProviders::Imagen => write!(f, "imagen"),
+            #[cfg(feature = "replicate")]
+            CliLvmProviders::Replicate => write!(f, "replicate"),
+            #[cfg(feature = "openai-compatible")]
+            CliLvmProviders::OpenAiCompatible => write!(f, "open-ai-compatible"),
+            CliLvmProviders::Custom => write!(f, "custom"),
         }
     }
 }
@@ -43,7 +90,54 @@ impl Cli {
             CliLvmProviders::Automatic1111 => {
                 LvmProviders::Automatic1111(self.provider_configuration.clone())
             }
+            CliLvmProviders::Automatic1111Pool => {
+                LvmProviders::Automatic1111Pool(Automatic1111PoolConfiguration {
+                    provider: ProviderConfiguration {
+                        base_url: None,
+                        ..self.provider_configuration.clone()
+                    },
+                    base_urls: self
+                        .provider_configuration
+                        .base_url
+                        .iter()
+                        .flat_map(|base_urls| base_urls.split(','))
+                        .map(|base_url| base_url.trim().to_string())
+                        .collect(),
+                    ..Default::default()
+                })
+            }
             CliLvmProviders::XAi => LvmProviders::XAi(self.provider_configuration.clone()),
+            #[cfg(feature = "stability")]
+            CliLvmProviders::Stability => {
+                LvmProviders::Stability(self.provider_configuration.clone())
+            }
+            #[cfg(feature = "comfyui")]
+            CliLvmProviders::ComfyUi => LvmProviders::ComfyUi(self.provider_configuration.clone()),
+            #[cfg(feature = "invokeai")]
+            CliLvmProviders::InvokeAi => {
+                LvmProviders::InvokeAi(self.provider_configuration.clone())
+            }
+            #[cfg(feature = "huggingface")]
+            CliLvmProviders::HuggingFace => {
+                LvmProviders::HuggingFace(self.provider_configuration.clone())
+            }
+            #[cfg(feature = "imagen")]
+            CliLvmProviders::Imagen => LvmProviders::Imagen(self.provider_configuration.clone()),
+            #[cfg(feature = "replicate")]
+            CliLvmProviders::Replicate => LvmProviders::Replicate(ReplicateConfiguration {
+                provider: self.provider_configuration.clone(),
+                ..Default::default()
+            }),
+            #[cfg(feature = "openai-compatible")]
+            CliLvmProviders::OpenAiCompatible => {
+                LvmProviders::OpenAiCompatible(OpenAiCompatibleConfiguration {
+                    provider: self.provider_configuration.clone(),
+                    ..Default::default()
+                })
+            }
+            CliLvmProviders::Custom => {
+                LvmProviders::Registered(self.custom_provider.clone().unwrap_or_default())
+            }
         }
     }
 }
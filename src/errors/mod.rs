@@ -1,4 +1,5 @@
 use crate::parameters::provider::ProviderConfiguration;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub enum LvmError {}
@@ -19,3 +20,165 @@ impl std::fmt::Display for ProviderConfigurationError {
         )
     }
 }
+
+impl std::error::Error for ProviderConfigurationError {}
+
+/// The provider refused to generate the image because of its content policy.
+/// Providers implemented outside of this crate can return it so the failure is classified as [`ErrorKind::ContentRejected`].
+#[derive(Debug)]
+pub struct ContentRejectedError {
+    pub message: String,
+}
+
+impl std::fmt::Display for ContentRejectedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ContentRejectedError {}
+
+/// The kind of failure behind an error returned by a provider, used to decide whether another provider should be tried.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The provider is not configured correctly, e.g. its API key is missing.
+    Configuration,
+    /// The provider could not be reached.
+    Connection,
+    /// The provider took too long to respond.
+    Timeout,
+    /// The provider rejected the request because too many requests were sent.
+    RateLimited,
+    /// The provider failed to handle the request.
+    ServerError,
+    /// The provider refused to generate the image because of its content policy.
+    ContentRejected,
+    /// The provider rejected the request as invalid.
+    InvalidRequest,
+    Other,
+}
+
+impl ErrorKind {
+    /// The name of the kind, as it is serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Configuration => "configuration",
+            ErrorKind::Connection => "connection",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::ServerError => "server_error",
+            ErrorKind::ContentRejected => "content_rejected",
+            ErrorKind::InvalidRequest => "invalid_request",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// Classify an error returned by a provider.
+    /// Providers report most failures as messages, so the status in the message is used if the error has no known type.
+    pub fn of(error: &anyhow::Error) -> ErrorKind {
+        for cause in error.chain() {
+            if cause.is::<ProviderConfigurationError>() {
+                return ErrorKind::Configuration;
+            }
+            if cause.is::<ContentRejectedError>() {
+                return ErrorKind::ContentRejected;
+            }
+            if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+                if error.is_timeout() {
+                    return ErrorKind::Timeout;
+                }
+                if error.is_connect() {
+                    return ErrorKind::Connection;
+                }
+                if let Some(status) = error.status() {
+                    return ErrorKind::from_status(status.as_u16());
+                }
+            }
+            if cause.is::<tokio::time::error::Elapsed>() {
+                return ErrorKind::Timeout;
+            }
+            #[cfg(any(feature = "openai", feature = "openai-compatible"))]
+            if let Some(async_openai::error::OpenAIError::ApiError(error)) =
+                cause.downcast_ref::<async_openai::error::OpenAIError>()
+            {
+                return match error.code.as_deref() {
+                    Some("content_policy_violation") => ErrorKind::ContentRejected,
+                    Some("rate_limit_exceeded") => ErrorKind::RateLimited,
+                    _ => ErrorKind::InvalidRequest,
+                };
+            }
+        }
+        ErrorKind::from_message(&format!("{:#}", error))
+    }
+
+    fn from_status(status: u16) -> ErrorKind {
+        match status {
+            408 | 504 => ErrorKind::Timeout,
+            429 => ErrorKind::RateLimited,
+            500..=599 => ErrorKind::ServerError,
+            400..=499 => ErrorKind::InvalidRequest,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Content rejections aren't guessed from the message, since response bodies often mention words like "safety".
+    /// Providers return a [`ContentRejectedError`] instead.
+    fn from_message(message: &str) -> ErrorKind {
+        // Providers include the status of failed responses as `Status: 500 Internal Server Error`.
+        if let Some((_, status)) = message.split_once("Status: ")
+            && let Some(Ok(status)) = status.get(..3).map(str::parse::<u16>)
+        {
+            return ErrorKind::from_status(status);
+        }
+        if message.to_lowercase().contains("timed out") {
+            return ErrorKind::Timeout;
+        }
+        ErrorKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_error_kind() {
+        let configuration_error = anyhow!(ProviderConfigurationError {
+            message: "XAI_API_KEY environment variable not set".to_string(),
+            configuration: ProviderConfiguration::default(),
+        });
+        assert_eq!(
+            ErrorKind::of(&configuration_error),
+            ErrorKind::Configuration
+        );
+        assert_eq!(
+            ErrorKind::of(&anyhow!(
+                "Failed to generate image. Status: 429 Too Many Requests, Response: \"\""
+            )),
+            ErrorKind::RateLimited
+        );
+        assert_eq!(
+            ErrorKind::of(&anyhow!("Failed. Status: 503 Service Unavailable")),
+            ErrorKind::ServerError
+        );
+        assert_eq!(
+            ErrorKind::of(&anyhow!(
+                "Failed. Status: 502 Bad Gateway, Response: \"Filtered by the safety proxy\""
+            )),
+            ErrorKind::ServerError
+        );
+        assert_eq!(
+            ErrorKind::of(&anyhow!(ContentRejectedError {
+                message: "NSFW content detected".to_string(),
+            })),
+            ErrorKind::ContentRejected
+        );
+        assert_eq!(
+            ErrorKind::of(&anyhow!("Prompt 1234 timed out.")),
+            ErrorKind::Timeout
+        );
+        assert_eq!(ErrorKind::of(&anyhow!("Something else")), ErrorKind::Other);
+    }
+}
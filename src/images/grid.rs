@@ -0,0 +1,388 @@
+//! Arrange images in a labelled grid.
+
+use super::{LvmImage, LvmImageMetadata, font};
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const EMPTY_CELL: Rgba<u8> = Rgba([224, 224, 224, 255]);
+const TEXT: Rgba<u8> = Rgba([0, 0, 0, 255]);
+/// The size of cells if there are no images to size them by.
+const DEFAULT_CELL_SIZE: u32 = 256;
+/// Captions longer than this many lines are cut short.
+const MAX_CAPTION_LINES: usize = 3;
+
+/// Images arranged in rows, with labels above the columns and to the left of the rows.
+#[derive(Default)]
+pub(crate) struct Grid {
+    /// The images in row order. Missing images are drawn as empty cells.
+    pub cells: Vec<Option<RgbaImage>>,
+    pub columns: usize,
+    pub column_labels: Vec<String>,
+    pub row_labels: Vec<String>,
+    /// Text drawn under each image, in the same order as the images.
+    pub captions: Vec<Option<String>>,
+    /// Text drawn above the column labels.
+    pub title: Option<String>,
+}
+
+impl Grid {
+    /// Draw the grid. Cells are the size of the largest image, and smaller images are scaled to fit.
+    pub(crate) fn render(&self) -> RgbaImage {
+        let columns = self.columns.max(1) as u32;
+        let rows = self.cells.len().div_ceil(columns as usize).max(1) as u32;
+        let (cell_width, cell_height) = self
+            .cells
+            .iter()
+            .flatten()
+            .map(|image| image.dimensions())
+            .reduce(|(w1, h1), (w2, h2)| (w1.max(w2), h1.max(h2)))
+            .unwrap_or((DEFAULT_CELL_SIZE, DEFAULT_CELL_SIZE));
+        let scale = (cell_width.min(cell_height) / 256).max(1);
+        let padding = 4 * scale;
+        let line_height = font::LINE_HEIGHT * scale;
+
+        let wrap = |labels: &[String], width: u32| -> Vec<Vec<String>> {
+            labels
+                .iter()
+                .map(|label| font::wrap(label, width.saturating_sub(2 * padding), scale))
+                .collect()
+        };
+        let lines_height = |lines: &[Vec<String>]| {
+            let count = lines.iter().map(Vec::len).max().unwrap_or(0) as u32;
+            if count == 0 {
+                0
+            } else {
+                count * line_height + 2 * padding
+            }
+        };
+
+        // Row labels get up to half a cell of width, but no more than they need.
+        let row_label_width = self
+            .row_labels
+            .iter()
+            .flat_map(|label| label.lines())
+            .map(|line| font::text_width(line, scale) + 2 * padding)
+            .max()
+            .map_or(0, |width| {
+                width
+                    .min(cell_width / 2)
+                    .max(font::ADVANCE * scale + 2 * padding)
+            });
+        let row_lines = wrap(&self.row_labels, row_label_width.max(2 * padding + 1));
+        let column_lines = wrap(&self.column_labels, cell_width);
+        let column_label_height = lines_height(&column_lines);
+        let caption_lines: Vec<Vec<String>> = self
+            .captions
+            .iter()
+            .map(|caption| {
+                let Some(caption) = caption else {
+                    return Vec::new();
+                };
+                let mut lines = font::wrap(caption, cell_width.saturating_sub(2 * padding), scale);
+                if lines.len() > MAX_CAPTION_LINES {
+                    lines.truncate(MAX_CAPTION_LINES);
+                    if let Some(last) = lines.last_mut() {
+                        let kept = last.chars().count().saturating_sub(3);
+                        *last = last.chars().take(kept).chain("...".chars()).collect();
+                    }
+                }
+                lines
+            })
+            .collect();
+        let row_height = cell_height + lines_height(&caption_lines);
+        let width = row_label_width + columns * cell_width;
+        let title_lines = self
+            .title
+            .as_deref()
+            .map(|title| font::wrap(title, width.saturating_sub(2 * padding).max(1), scale))
+            .unwrap_or_default();
+        let title_height = lines_height(std::slice::from_ref(&title_lines));
+        let top = title_height + column_label_height;
+        let height = top + rows * row_height;
+
+        let mut canvas = RgbaImage::from_pixel(width, height, BACKGROUND);
+        for (index, line) in title_lines.iter().enumerate() {
+            let x = width.saturating_sub(font::text_width(line, scale)) / 2;
+            font::draw_text(
+                &mut canvas,
+                x,
+                padding + index as u32 * line_height,
+                line,
+                scale,
+                TEXT,
+            );
+        }
+        for (column, lines) in column_lines.iter().enumerate().take(columns as usize) {
+            let left = row_label_width + column as u32 * cell_width;
+            for (index, line) in lines.iter().enumerate() {
+                let x = left + cell_width.saturating_sub(font::text_width(line, scale)) / 2;
+                let y = title_height + padding + index as u32 * line_height;
+                font::draw_text(&mut canvas, x, y, line, scale, TEXT);
+            }
+        }
+        for (row, lines) in row_lines.iter().enumerate().take(rows as usize) {
+            let block = lines.len() as u32 * line_height;
+            let y = top + row as u32 * row_height + cell_height.saturating_sub(block) / 2;
+            for (index, line) in lines.iter().enumerate() {
+                let x = row_label_width.saturating_sub(font::text_width(line, scale)) / 2;
+                font::draw_text(
+                    &mut canvas,
+                    x,
+                    y + index as u32 * line_height,
+                    line,
+                    scale,
+                    TEXT,
+                );
+            }
+        }
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let x = row_label_width + column * cell_width;
+                let y = top + row * row_height;
+                let index = (row * columns + column) as usize;
+                for (line_index, line) in caption_lines.get(index).into_iter().flatten().enumerate()
+                {
+                    let x = x + cell_width.saturating_sub(font::text_width(line, scale)) / 2;
+                    let y = y + cell_height + padding + line_index as u32 * line_height;
+                    font::draw_text(&mut canvas, x, y, line, scale, TEXT);
+                }
+                match self.cells.get(index) {
+                    Some(Some(image)) => {
+                        let image = if image.dimensions() == (cell_width, cell_height) {
+                            image.clone()
+                        } else {
+                            DynamicImage::ImageRgba8(image.clone())
+                                .resize(cell_width, cell_height, FilterType::Lanczos3)
+                                .to_rgba8()
+                        };
+                        let dx = (cell_width - image.width()) / 2;
+                        let dy = (cell_height - image.height()) / 2;
+                        image::imageops::overlay(
+                            &mut canvas,
+                            &image,
+                            (x + dx).into(),
+                            (y + dy).into(),
+                        );
+                    }
+                    Some(None) => {
+                        for py in y..y + cell_height {
+                            for px in x..x + cell_width {
+                                canvas.put_pixel(px, py, EMPTY_CELL);
+                            }
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        canvas
+    }
+}
+
+/// What to write under each image of a contact sheet, taken from its metadata.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Caption {
+    /// The name of the provider that generated the image.
+    Provider,
+    /// The size the image was generated at, e.g. `1024x768`.
+    Size,
+    /// The generation parameters or revised prompt reported by the provider.
+    GenerationParams,
+    /// The provider, size and any post-processing of the image.
+    Summary,
+}
+
+impl Caption {
+    /// The caption of an image, or `None` if its metadata doesn't have it.
+    pub fn text(&self, metadata: &LvmImageMetadata) -> Option<String> {
+        let size = || Some(format!("{}x{}", metadata.width?, metadata.height?));
+        match self {
+            Caption::Provider => metadata.provider.clone(),
+            Caption::Size => size(),
+            Caption::GenerationParams => metadata
+                .generation_params
+                .clone()
+                .filter(|params| !params.is_empty()),
+            Caption::Summary => {
+                let parts: Vec<String> = [
+                    metadata.provider.clone(),
+                    size(),
+                    metadata.post_processing.clone(),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                (!parts.is_empty()).then(|| parts.join(", "))
+            }
+        }
+    }
+}
+
+/// Arranges images into a single image, like Automatic1111's grids, so they can be compared at a glance.
+/// Images are placed in rows from left to right, and are scaled to fit cells the size of the largest image.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ContactSheet {
+    /// The number of images in each row. If not set, the grid is as close to square as possible.
+    #[serde(default)]
+    pub columns: Option<usize>,
+    /// Labels above each column.
+    #[serde(default)]
+    pub column_labels: Vec<String>,
+    /// Labels to the left of each row.
+    #[serde(default)]
+    pub row_labels: Vec<String>,
+    /// What to write under each image.
+    #[serde(default)]
+    pub caption: Option<Caption>,
+    /// Text above the whole sheet.
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl ContactSheet {
+    pub fn new() -> Self {
+        ContactSheet::default()
+    }
+
+    pub fn with_columns(mut self, columns: usize) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    pub fn with_column_labels<S: Into<String>>(
+        mut self,
+        labels: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.column_labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_row_labels<S: Into<String>>(mut self, labels: impl IntoIterator<Item = S>) -> Self {
+        self.row_labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_caption(mut self, caption: Caption) -> Self {
+        self.caption = Some(caption);
+        self
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /// Arrange the images into a single PNG image.
+    pub fn render(&self, images: &[LvmImage]) -> Result<LvmImage> {
+        if images.is_empty() {
+            return Err(anyhow!("A contact sheet needs at least one image."));
+        }
+        let columns = self
+            .columns
+            .unwrap_or_else(|| (images.len() as f64).sqrt().ceil() as usize)
+            .max(1);
+        let grid = Grid {
+            cells: images
+                .iter()
+                .map(|image| image.to_rgba().map(Some))
+                .collect::<Result<_>>()?,
+            columns,
+            column_labels: self.column_labels.clone(),
+            row_labels: self.row_labels.clone(),
+            captions: images
+                .iter()
+                .map(|image| self.caption?.text(image.metadata.as_ref()?))
+                .collect(),
+            title: self.title.clone(),
+        };
+        let mut sheet = LvmImage::from_rgba(grid.render())?;
+        sheet.metadata_mut().post_processing =
+            Some(format!("contact sheet of {} images", images.len()));
+        Ok(sheet)
+    }
+}
+
+impl LvmImage {
+    /// Decode the image into pixels.
+    pub(crate) fn to_rgba(&self) -> Result<RgbaImage> {
+        Ok(image::load_from_memory(&self.decode()?)?.to_rgba8())
+    }
+
+    /// Encode pixels as a base64-encoded PNG.
+    pub(crate) fn from_rgba(image: RgbaImage) -> Result<LvmImage> {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(LvmImage {
+            data: base64::prelude::BASE64_STANDARD
+                .encode(png)
+                .as_bytes()
+                .to_vec(),
+            metadata: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let red = RgbaImage::from_pixel(64, 32, Rgba([255, 0, 0, 255]));
+        let grid = Grid {
+            cells: vec![Some(red.clone()), None, Some(red)],
+            columns: 2,
+            column_labels: vec!["5".to_string(), "7".to_string()],
+            row_labels: vec!["20".to_string(), "30".to_string()],
+            title: Some("Euler a".to_string()),
+            ..Default::default()
+        };
+        let image = LvmImage::from_rgba(grid.render())?.to_rgba()?;
+        // Two 64 pixel columns, plus the row labels with 4 pixels of padding on each side.
+        let left = font::text_width("20", 1) + 8;
+        assert_eq!(image.width(), left + 2 * 64);
+        // Two 32 pixel rows, plus a padded line each for the title and the column labels.
+        let top = 2 * (font::LINE_HEIGHT + 8);
+        assert_eq!(image.height(), top + 2 * 32);
+        assert_eq!(*image.get_pixel(left, top), Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(left + 64, top), EMPTY_CELL);
+        assert_eq!(*image.get_pixel(left + 64, top + 32), BACKGROUND);
+        Ok(())
+    }
+
+    #[test]
+    fn test_contact_sheet() -> Result<()> {
+        let mut image = LvmImage::from_rgba(RgbaImage::from_pixel(40, 40, BACKGROUND))?;
+        *image.metadata_mut() = LvmImageMetadata {
+            provider: Some("comfy-ui".to_string()),
+            width: Some(40),
+            height: Some(40),
+            ..Default::default()
+        };
+        let images = vec![image; 5];
+
+        // Five images are arranged in three columns and two rows.
+        let sheet = ContactSheet::new().render(&images)?.to_rgba()?;
+        assert_eq!(sheet.dimensions(), (3 * 40, 2 * 40));
+
+        let sheet = ContactSheet::new()
+            .with_columns(5)
+            .with_caption(Caption::Summary)
+            .render(&images)?;
+        assert_eq!(
+            Caption::Summary.text(images[0].metadata.as_ref().unwrap()),
+            Some("comfy-ui, 40x40".to_string())
+        );
+        // Each caption is wrapped onto three lines in a 40 pixel cell.
+        let caption_height = 3 * font::LINE_HEIGHT + 8;
+        assert_eq!(sheet.to_rgba()?.dimensions(), (5 * 40, 40 + caption_height));
+        assert!(ContactSheet::new().render(&[]).is_err());
+        Ok(())
+    }
+}
@@ -1,10 +1,18 @@
 use anyhow::Result;
 use async_openai::types::Image;
 use base64::Engine;
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "image")]
+mod font;
+#[cfg(feature = "image")]
+pub(crate) mod grid;
+#[cfg(feature = "image")]
+mod resize;
+
 /// An image generated by an LVM provider
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LvmImage {
     /// The base64-encoded image data
     pub data: Vec<u8>,
@@ -13,9 +21,20 @@ pub struct LvmImage {
 }
 
 /// Metadata associated with an LVM image
-#[derive(Debug)]
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
 pub struct LvmImageMetadata {
     pub generation_params: Option<String>,
+    /// The width in pixels the provider was asked to generate.
+    pub width: Option<u32>,
+    /// The height in pixels the provider was asked to generate.
+    pub height: Option<u32>,
+    /// A description of how the image was changed after it was generated, e.g. `crop 1024x1024 to 1000x750`.
+    pub post_processing: Option<String>,
+    /// The name of the provider that generated the image, e.g. `open-ai`.
+    pub provider: Option<String>,
+    /// The tokens used to generate the image, for models billed by token.
+    /// Providers that report tokens per response set this on the first image of the response.
+    pub tokens: Option<u32>,
 }
 
 impl From<Image> for LvmImage {
@@ -28,6 +47,7 @@ impl From<Image> for LvmImage {
                 data: Vec::new(),
                 metadata: Some(LvmImageMetadata {
                     generation_params: Some(revised_prompt.unwrap_or_default()),
+                    ..Default::default()
                 }),
             },
             Image::B64Json {
@@ -37,6 +57,7 @@ impl From<Image> for LvmImage {
                 data: b64_json.as_bytes().to_vec(),
                 metadata: Some(LvmImageMetadata {
                     generation_params: Some(revised_prompt.unwrap_or_default()),
+                    ..Default::default()
                 }),
             },
         }
@@ -44,16 +65,25 @@ impl From<Image> for LvmImage {
 }
 
 impl LvmImage {
+    /// Get the metadata of the image, creating empty metadata if there is none.
+    pub fn metadata_mut(&mut self) -> &mut LvmImageMetadata {
+        self.metadata.get_or_insert_with(LvmImageMetadata::default)
+    }
+
     /// Save the image to a file and return the path to the file.
     pub fn to_file(&self, path: &Path) -> Result<PathBuf> {
         // Check that file_path is not a directory.
         if path.is_dir() {
             return Err(anyhow::anyhow!("file_path must be a file path."));
         }
-        let image = base64::prelude::BASE64_STANDARD.decode(self.data.clone())?;
-        std::fs::write(path, image)?;
+        std::fs::write(path, self.decode()?)?;
         Ok(path.to_path_buf())
     }
+
+    /// Decode the base64-encoded image data.
+    pub fn decode(&self) -> Result<Vec<u8>> {
+        Ok(base64::prelude::BASE64_STANDARD.decode(&self.data)?)
+    }
 }
 
 #[cfg(test)]
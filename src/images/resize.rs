@@ -0,0 +1,105 @@
+//! Resize generated images to an exact size.
+
+use super::LvmImage;
+use crate::parameters::size::ResizeMode;
+use anyhow::Result;
+use base64::Engine;
+use image::{DynamicImage, ImageFormat, RgbaImage, imageops::FilterType};
+use std::io::Cursor;
+
+impl LvmImage {
+    /// Resize the image to exactly `width`x`height` pixels and record the change in the metadata.
+    /// Images that are already the right size are left untouched.
+    pub fn resize_to(&mut self, width: u32, height: u32, mode: ResizeMode) -> Result<()> {
+        let image = image::load_from_memory(&self.decode()?)?;
+        let (original_width, original_height) = (image.width(), image.height());
+        if (original_width, original_height) == (width, height) {
+            return Ok(());
+        }
+
+        let resized = match mode {
+            ResizeMode::Stretch => image.resize_exact(width, height, FilterType::Lanczos3),
+            ResizeMode::Crop => image.resize_to_fill(width, height, FilterType::Lanczos3),
+            ResizeMode::Pad => {
+                let fitted = image.resize(width, height, FilterType::Lanczos3);
+                let mut canvas = RgbaImage::new(width, height);
+                let x = (width - fitted.width()) / 2;
+                let y = (height - fitted.height()) / 2;
+                image::imageops::overlay(&mut canvas, &fitted.to_rgba8(), x.into(), y.into());
+                DynamicImage::ImageRgba8(canvas)
+            }
+        };
+
+        let mut png = Vec::new();
+        resized.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        self.data = base64::prelude::BASE64_STANDARD
+            .encode(png)
+            .as_bytes()
+            .to_vec();
+        self.metadata_mut().post_processing = Some(format!(
+            "{} {}x{} to {}x{}",
+            mode, original_width, original_height, width, height
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a base64-encoded PNG of the given size filled with opaque white.
+    fn white_image(width: u32, height: u32) -> LvmImage {
+        let image = RgbaImage::from_pixel(width, height, image::Rgba([255, 255, 255, 255]));
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        LvmImage {
+            data: base64::prelude::BASE64_STANDARD
+                .encode(png)
+                .as_bytes()
+                .to_vec(),
+            metadata: None,
+        }
+    }
+
+    fn load(image: &LvmImage) -> RgbaImage {
+        image::load_from_memory(&image.decode().unwrap())
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn test_resize_to_crop() {
+        let mut image = white_image(40, 20);
+        image.resize_to(10, 10, ResizeMode::Crop).unwrap();
+        let resized = load(&image);
+        assert_eq!(resized.dimensions(), (10, 10));
+        assert_eq!(resized.get_pixel(0, 0)[3], 255);
+        assert_eq!(
+            image.metadata.unwrap().post_processing,
+            Some("crop 40x20 to 10x10".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resize_to_pad() {
+        let mut image = white_image(40, 20);
+        image.resize_to(10, 10, ResizeMode::Pad).unwrap();
+        let resized = load(&image);
+        assert_eq!(resized.dimensions(), (10, 10));
+        // The image is scaled to 10x5 and centred, so the top and bottom rows are padding.
+        assert_eq!(resized.get_pixel(0, 0)[3], 0);
+        assert_eq!(resized.get_pixel(5, 5)[3], 255);
+    }
+
+    #[test]
+    fn test_resize_to_same_size() {
+        let mut image = white_image(10, 10);
+        let data = image.data.clone();
+        image.resize_to(10, 10, ResizeMode::Stretch).unwrap();
+        assert_eq!(image.data, data);
+        assert!(image.metadata.is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! Record metrics with the `metrics` crate, if the `metrics` feature is enabled.
+//!
+//! Nothing is exported by this crate. Install a recorder, e.g. `metrics-exporter-prometheus`,
+//! to collect them. All metrics are labelled with the name of the provider, as returned by `LvmProviders::name`.
+//! Without the feature, these functions do nothing.
+
+#[cfg(feature = "metrics")]
+use crate::errors::ErrorKind;
+use std::time::Duration;
+
+/// Requests sent to a provider, including the ones that failed.
+#[cfg(feature = "metrics")]
+pub const REQUESTS: &str = "lvm_requests_total";
+/// How long requests took, in seconds.
+#[cfg(feature = "metrics")]
+pub const REQUEST_DURATION: &str = "lvm_request_duration_seconds";
+/// Images generated by a provider.
+#[cfg(feature = "metrics")]
+pub const IMAGES: &str = "lvm_images_total";
+/// Errors returned by a provider, also labelled with their kind.
+#[cfg(feature = "metrics")]
+pub const FAILURES: &str = "lvm_failures_total";
+/// Requests sent again, to the next provider of a fallback chain or the next instance of a pool.
+#[cfg(feature = "metrics")]
+pub const RETRIES: &str = "lvm_retries_total";
+/// How long Automatic1111 tasks waited in the queue before they started, in seconds.
+#[cfg(feature = "metrics")]
+pub const QUEUE_WAIT: &str = "lvm_queue_wait_seconds";
+
+/// Describe the metrics to the installed recorder, so exporters can include their units and descriptions.
+#[cfg(feature = "metrics")]
+pub fn describe_metrics() {
+    use ::metrics::{Unit, describe_counter, describe_histogram};
+    describe_counter!(
+        REQUESTS,
+        "Requests sent to a provider, including the ones that failed."
+    );
+    describe_histogram!(REQUEST_DURATION, Unit::Seconds, "How long requests took.");
+    describe_counter!(IMAGES, "Images generated by a provider.");
+    describe_counter!(FAILURES, "Errors returned by a provider, by kind.");
+    describe_counter!(
+        RETRIES,
+        "Requests sent again to another provider or instance."
+    );
+    describe_histogram!(
+        QUEUE_WAIT,
+        Unit::Seconds,
+        "How long Automatic1111 tasks waited in the queue before they started."
+    );
+}
+
+/// Record a request sent to a provider, with the images and errors it returned.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_request(
+    provider: &str,
+    duration: Duration,
+    images: usize,
+    errors: &[anyhow::Error],
+) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!(REQUESTS, "provider" => provider.to_string()).increment(1);
+        ::metrics::histogram!(REQUEST_DURATION, "provider" => provider.to_string())
+            .record(duration.as_secs_f64());
+        ::metrics::counter!(IMAGES, "provider" => provider.to_string()).increment(images as u64);
+        for error in errors {
+            ::metrics::counter!(FAILURES, "provider" => provider.to_string(), "kind" => ErrorKind::of(error).as_str())
+                .increment(1);
+        }
+    }
+}
+
+/// Record a request being sent again by a provider.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_retry(provider: &str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(RETRIES, "provider" => provider.to_string()).increment(1);
+}
+
+/// Record how long a task waited in a provider's queue.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn record_queue_wait(provider: &str, wait: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(QUEUE_WAIT, "provider" => provider.to_string())
+        .record(wait.as_secs_f64());
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use ::metrics::{
+        Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    };
+
+    type CounterValue = (Key, Arc<AtomicU64>);
+
+    /// Keeps the value of each counter, by its name and labels.
+    #[derive(Clone, Default)]
+    struct Counters(Arc<Mutex<Vec<CounterValue>>>);
+
+    struct AtomicCounter(Arc<AtomicU64>);
+
+    impl CounterFn for AtomicCounter {
+        fn increment(&self, value: u64) {
+            self.0.fetch_add(value, Ordering::SeqCst);
+        }
+        fn absolute(&self, value: u64) {
+            self.0.store(value, Ordering::SeqCst);
+        }
+    }
+
+    impl Counters {
+        fn get(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+            let counters = self.0.lock().unwrap_or_else(|p| p.into_inner());
+            counters
+                .iter()
+                .filter(|(key, _)| {
+                    key.name() == name
+                        && labels.iter().all(|(label, value)| {
+                            key.labels()
+                                .any(|l| l.key() == *label && l.value() == *value)
+                        })
+                })
+                .map(|(_, value)| value.load(Ordering::SeqCst))
+                .sum()
+        }
+    }
+
+    impl Recorder for Counters {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+            let mut counters = self.0.lock().unwrap_or_else(|p| p.into_inner());
+            let value = match counters.iter().find(|(k, _)| k == key) {
+                Some((_, value)) => Arc::clone(value),
+                None => {
+                    let value = Arc::new(AtomicU64::new(0));
+                    counters.push((key.clone(), Arc::clone(&value)));
+                    value
+                }
+            };
+            Counter::from_arc(Arc::new(AtomicCounter(value)))
+        }
+        fn register_gauge(&self, _key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+        fn register_histogram(&self, _key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn test_record_request() {
+        let counters = Counters::default();
+        ::metrics::with_local_recorder(&counters, || {
+            describe_metrics();
+            record_request(
+                "x-ai",
+                Duration::from_millis(20),
+                2,
+                &[anyhow::anyhow!("Failed. Status: 429 Too Many Requests")],
+            );
+            record_retry("fallback");
+        });
+        assert_eq!(counters.get(REQUESTS, &[("provider", "x-ai")]), 1);
+        assert_eq!(counters.get(IMAGES, &[("provider", "x-ai")]), 2);
+        assert_eq!(
+            counters.get(FAILURES, &[("provider", "x-ai"), ("kind", "rate_limited")]),
+            1
+        );
+        assert_eq!(counters.get(RETRIES, &[("provider", "fallback")]), 1);
+    }
+}
@@ -0,0 +1,164 @@
+//! Report what the library does with `tracing`, if the `tracing` feature is enabled.
+//!
+//! Provider dispatch is recorded in an `info` span with the provider, model and number of images,
+//! followed by an event with the latency and how many images and errors there were.
+//! HTTP requests are recorded at the `debug` level, in a span for each poll iteration when polling a task.
+//! Without the feature, warnings are printed to stderr and everything else is left out.
+
+pub mod metrics;
+
+use crate::providers::middleware;
+use reqwest::{RequestBuilder, Response};
+use std::future::Future;
+
+/// Record an event at a level, e.g. `event!(debug, task_id = %id, "Polled task")`.
+/// Nothing is recorded without the `tracing` feature, so arguments must not have side effects.
+macro_rules! event {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)*);
+    }};
+}
+pub(crate) use event;
+
+/// Record a warning, or print it to stderr without the `tracing` feature.
+/// Takes a format string and its arguments, optionally after fields,
+/// e.g. `warning!(cfg_scale = value, "Could not convert cfg_scale")`. Field values must implement `Debug`.
+macro_rules! warning {
+    ($($field:ident = $value:expr,)+ $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($field = ?$value,)+ $($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!(
+            "{}{}",
+            format_args!($($arg)+),
+            [$(format!(" {}={:?}", stringify!($field), $value)),+].concat()
+        );
+    }};
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!($($arg)*);
+    }};
+}
+pub(crate) use warning;
+
+/// Run a future in a span, e.g. `in_span!(future, tracing::info_span!("name"))`.
+/// Without the `tracing` feature the span isn't created and the future is returned as is.
+macro_rules! in_span {
+    ($future:expr, $span:expr) => {{
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument($future, $span);
+        #[cfg(not(feature = "tracing"))]
+        let future = $future;
+        future
+    }};
+}
+pub(crate) use in_span;
+
+/// Send HTTP requests with the headers added by middleware,
+/// in a span recording their method, URL, status and latency.
+pub(crate) trait SendTraced {
+    fn send_traced(self) -> impl Future<Output = reqwest::Result<Response>> + Send;
+}
+
+impl SendTraced for RequestBuilder {
+    #[cfg(not(feature = "tracing"))]
+    fn send_traced(self) -> impl Future<Output = reqwest::Result<Response>> + Send {
+        with_middleware_headers(self).send()
+    }
+
+    #[cfg(feature = "tracing")]
+    fn send_traced(self) -> impl Future<Output = reqwest::Result<Response>> + Send {
+        use tracing::Instrument;
+        async move {
+            let (client, request) = with_middleware_headers(self).build_split();
+            let request = request?;
+            let span = tracing::debug_span!(
+                "http_request",
+                method = %request.method(),
+                url = %request.url(),
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+            );
+            let start = std::time::Instant::now();
+            let response = client.execute(request).instrument(span.clone()).await;
+            span.record("latency_ms", start.elapsed().as_millis() as u64);
+            match &response {
+                Ok(response) => {
+                    span.record("status", response.status().as_u16());
+                    tracing::debug!(parent: &span, "HTTP request finished");
+                }
+                Err(error) => tracing::warn!(parent: &span, %error, "HTTP request failed"),
+            }
+            response
+        }
+    }
+}
+
+/// Add the headers set by middleware for the request being sent, if there are any.
+fn with_middleware_headers(request: RequestBuilder) -> RequestBuilder {
+    match middleware::current_headers() {
+        Some(headers) => request.headers(headers),
+        None => request,
+    }
+}
+
+/// Send a GET request to a URL, like `reqwest::get`.
+pub(crate) fn get(url: &str) -> impl Future<Output = reqwest::Result<Response>> + Send {
+    reqwest::Client::new().get(url).send_traced()
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::subscriber::with_default;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::method};
+
+    /// Records the names of the spans that are created.
+    #[derive(Clone, Default)]
+    struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+    impl tracing::Subscriber for SpanNames {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut names = self.0.lock().unwrap_or_else(|p| p.into_inner());
+            names.push(span.metadata().name().to_string());
+            tracing::span::Id::from_u64(names.len() as u64)
+        }
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+        fn event(&self, _event: &tracing::Event<'_>) {}
+        fn enter(&self, _span: &tracing::span::Id) {}
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn test_send_traced() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(MockServer::start());
+        runtime.block_on(
+            Mock::given(method("GET"))
+                .respond_with(ResponseTemplate::new(204))
+                .mount(&server),
+        );
+
+        let subscriber = SpanNames::default();
+        let status = with_default(subscriber.clone(), || {
+            runtime.block_on(async { get(&server.uri()).await.map(|r| r.status()) })
+        })
+        .unwrap();
+        assert_eq!(status, 204);
+        assert!(
+            subscriber
+                .0
+                .lock()
+                .unwrap_or_else(|p| p.into_inner())
+                .contains(&"http_request".to_string())
+        );
+    }
+}
@@ -2,6 +2,7 @@
 
 mod errors;
 mod images;
+mod instrumentation;
 mod parameters;
 mod providers;
 mod traits;
@@ -9,16 +10,52 @@ mod traits;
 #[cfg(feature = "clap")]
 pub mod cli;
 
-pub use errors::LvmError;
-pub use images::LvmImage;
+pub use errors::{ContentRejectedError, ErrorKind, LvmError, ProviderConfigurationError};
+pub use images::{LvmImage, LvmImageMetadata};
 pub use parameters::{
+    field::RequestField,
     prompt::ImagePrompt,
     provider::ProviderConfiguration,
-    text_to_image::{TextToImageRequest, TextToImageRequestExtendedParameters},
+    size::{ImageSize, ResizeMode},
+    template::PromptTemplate,
+    text_to_image::{
+        ComfyUiRequestParameters, ImagenPersonGeneration, ImagenRequestParameters,
+        ImagenSafetySetting, OpenAiImageQuality, OpenAiImageStyle, OpenAiRequestParameters,
+        StabilityOutputFormat, StabilityRequestParameters, TextToImageRequest,
+        TextToImageRequestExtendedParameters, WorkflowVariable,
+    },
 };
-pub use providers::LvmProviders;
+pub use providers::{
+    BatchResults, LvmProviders,
+    cache::{CacheBackend, CachedProvider},
+    custom::{CustomProvider, register_provider, registered_provider, registered_provider_names},
+    fallback::{FallbackProvider, FallbackStep},
+    hedged::{HedgeStats, HedgedProvider},
+    middleware::{Middleware, MiddlewareProvider, RequestContext},
+    pricing::{Price, PricingTable},
+    shared::SharedProvider,
+    sweep::{Sweep, SweepAxis, SweepCell, SweepPosition, SweepResults},
+    usage::{MeteredProvider, Usage},
+};
+pub use traits::TextToImageProvider;
+
+#[cfg(feature = "image")]
+pub use images::grid::{Caption, ContactSheet};
+#[cfg(feature = "metrics")]
+pub use instrumentation::metrics;
+#[cfg(feature = "automatic1111")]
+pub use providers::automatic1111::pool::{Automatic1111PoolConfiguration, LoadBalancingStrategy};
+#[cfg(feature = "comfyui")]
+pub use providers::comfyui::workflow::{Binding, BindingSource, WorkflowTemplate};
+#[cfg(feature = "openai-compatible")]
+pub use providers::openai_compatible::{
+    AuthScheme, OpenAiCompatibleConfiguration, OpenAiRequestField,
+};
+#[cfg(feature = "replicate")]
+pub use providers::replicate::ReplicateConfiguration;
 
 #[cfg(test)]
 mod tests {
     use tokio as _;
+    use wiremock as _;
 }
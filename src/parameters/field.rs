@@ -0,0 +1,133 @@
+use crate::parameters::text_to_image::TextToImageRequest;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+/// A field of a [`TextToImageRequest`] that can be mapped onto a provider-specific input.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestField {
+    PositivePrompt,
+    NegativePrompt,
+    Model,
+    Width,
+    Height,
+    NumBatches,
+    BatchSize,
+    Steps,
+    SamplerName,
+    CfgScale,
+    Vae,
+    Seed,
+}
+
+impl RequestField {
+    /// Get the value of this field from a request, if it is set.
+    pub fn value(&self, request: &TextToImageRequest) -> Option<Value> {
+        let extended = request.extended.as_ref();
+        match self {
+            RequestField::PositivePrompt => request.prompt.positive_prompt.clone().map(Value::from),
+            RequestField::NegativePrompt => request.prompt.negative_prompt.clone().map(Value::from),
+            RequestField::Model => request.model.clone().map(Value::from),
+            RequestField::Width => request.width.map(Value::from),
+            RequestField::Height => request.height.map(Value::from),
+            RequestField::NumBatches => request.num_batches.map(Value::from),
+            RequestField::BatchSize => extended.and_then(|e| e.batch_size).map(Value::from),
+            RequestField::Steps => extended.and_then(|e| e.steps).map(Value::from),
+            RequestField::SamplerName => extended
+                .and_then(|e| e.sampler_name.clone())
+                .map(Value::from),
+            RequestField::CfgScale => extended.and_then(|e| e.cfg_scale).map(Value::from),
+            RequestField::Vae => extended.and_then(|e| e.vae.clone()).map(Value::from),
+            RequestField::Seed => extended.and_then(|e| e.seed).map(Value::from),
+        }
+    }
+
+    /// Set this field of a request to a value, or unset it if the value is `null`.
+    pub fn set_value(&self, request: &mut TextToImageRequest, value: &Value) -> Result<()> {
+        match self {
+            RequestField::PositivePrompt => request.prompt.positive_prompt = self.parse(value)?,
+            RequestField::NegativePrompt => request.prompt.negative_prompt = self.parse(value)?,
+            RequestField::Model => request.model = self.parse(value)?,
+            RequestField::Width => request.width = self.parse(value)?,
+            RequestField::Height => request.height = self.parse(value)?,
+            RequestField::NumBatches => request.num_batches = self.parse(value)?,
+            RequestField::BatchSize => {
+                request.extended.get_or_insert_default().batch_size = self.parse(value)?
+            }
+            RequestField::Steps => {
+                request.extended.get_or_insert_default().steps = self.parse(value)?
+            }
+            RequestField::SamplerName => {
+                request.extended.get_or_insert_default().sampler_name = self.parse(value)?
+            }
+            RequestField::CfgScale => {
+                request.extended.get_or_insert_default().cfg_scale = self.parse(value)?
+            }
+            RequestField::Vae => {
+                request.extended.get_or_insert_default().vae = self.parse(value)?
+            }
+            RequestField::Seed => {
+                request.extended.get_or_insert_default().seed = self.parse(value)?
+            }
+        }
+        Ok(())
+    }
+
+    fn parse<T: DeserializeOwned>(&self, value: &Value) -> Result<T> {
+        serde_json::from_value(value.clone())
+            .with_context(|| format!("Invalid value for {}: {}", self, value))
+    }
+}
+
+impl std::fmt::Display for RequestField {
+    /// The name of the field, e.g. `cfg_scale`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(Value::String(name)) => write!(f, "{}", name),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::text_to_image::TextToImageRequestExtendedParameters;
+
+    #[test]
+    fn test_value() {
+        let request = TextToImageRequest {
+            width: Some(512),
+            extended: Some(TextToImageRequestExtendedParameters {
+                cfg_scale: Some(7.5),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(RequestField::Width.value(&request), Some(512.into()));
+        assert_eq!(RequestField::CfgScale.value(&request), Some(7.5.into()));
+        assert_eq!(RequestField::Seed.value(&request), None);
+        assert_eq!(RequestField::PositivePrompt.value(&request), None);
+    }
+
+    #[test]
+    fn test_set_value() -> Result<()> {
+        let mut request = TextToImageRequest::default();
+        RequestField::Steps.set_value(&mut request, &30.into())?;
+        RequestField::SamplerName.set_value(&mut request, &"Euler a".into())?;
+        assert_eq!(RequestField::Steps.value(&request), Some(30.into()));
+        assert_eq!(
+            RequestField::SamplerName.value(&request),
+            Some("Euler a".into())
+        );
+        RequestField::Steps.set_value(&mut request, &Value::Null)?;
+        assert_eq!(RequestField::Steps.value(&request), None);
+
+        let error = RequestField::Steps
+            .set_value(&mut request, &"many".into())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value for steps: \"many\"");
+        Ok(())
+    }
+}
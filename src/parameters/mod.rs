@@ -1,5 +1,8 @@
 //! Parameters used to generate images.
 
+pub mod field;
 pub mod prompt;
 pub mod provider;
+pub mod size;
+pub mod template;
 pub mod text_to_image;
@@ -13,4 +13,7 @@ pub struct ProviderConfiguration {
     /// The name of the API key environment variable.
     #[cfg_attr(feature = "clap", arg(long))]
     pub api_key_env_var: Option<String>,
+    /// The maximum number of requests to send to the provider at the same time when a request is split up.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub max_concurrent_requests: Option<u32>,
 }
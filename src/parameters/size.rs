@@ -0,0 +1,268 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[cfg(feature = "clap")]
+use clap::ValueEnum;
+
+/// The size of an image to generate.
+///
+/// Parsed from strings such as `1024x768` (exact), `~1000x750` (closest legal size) or `4:3` (aspect ratio).
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSize {
+    /// Exactly this many pixels. Providers that can't generate this size return an error.
+    Exact { width: u32, height: u32 },
+    /// The provider's preferred size with the aspect ratio closest to `width:height`.
+    AspectRatio { width: u32, height: u32 },
+    /// The legal size closest to this many pixels.
+    Closest { width: u32, height: u32 },
+}
+
+impl ImageSize {
+    fn dimensions(&self) -> (u32, u32) {
+        match *self {
+            ImageSize::Exact { width, height }
+            | ImageSize::AspectRatio { width, height }
+            | ImageSize::Closest { width, height } => (width, height),
+        }
+    }
+
+    /// The number of pixels requested, if this is not just an aspect ratio.
+    pub fn pixels(&self) -> Option<(u32, u32)> {
+        match *self {
+            ImageSize::Exact { width, height } | ImageSize::Closest { width, height } => {
+                Some((width, height))
+            }
+            ImageSize::AspectRatio { .. } => None,
+        }
+    }
+
+    /// Pick the size from a list of sizes supported by a provider.
+    pub fn resolve_from(&self, legal_sizes: &[(u32, u32)]) -> Result<(u32, u32)> {
+        let (width, height) = self.dimensions();
+        let best = match self {
+            ImageSize::Exact { .. } => legal_sizes.iter().find(|size| **size == (width, height)),
+            ImageSize::AspectRatio { .. } => legal_sizes.iter().min_by(|a, b| {
+                ratio_distance(**a, (width, height))
+                    .total_cmp(&ratio_distance(**b, (width, height)))
+            }),
+            ImageSize::Closest { .. } => legal_sizes
+                .iter()
+                .min_by_key(|(w, h)| w.abs_diff(width) + h.abs_diff(height)),
+        };
+        best.copied().ok_or_else(|| {
+            let legal_sizes: Vec<String> = legal_sizes
+                .iter()
+                .map(|(w, h)| format!("{}x{}", w, h))
+                .collect();
+            anyhow!(
+                "Image size {} is not supported. Supported sizes: {}",
+                self,
+                legal_sizes.join(", ")
+            )
+        })
+    }
+
+    /// Pick a size for providers that accept any dimensions that are a multiple of `multiple`.
+    /// Aspect ratios are scaled to roughly `default_area` pixels.
+    pub fn resolve_to_multiple(&self, multiple: u32, default_area: u32) -> Result<(u32, u32)> {
+        let (width, height) = self.dimensions();
+        match self {
+            ImageSize::Exact { .. } => {
+                if !width.is_multiple_of(multiple) || !height.is_multiple_of(multiple) {
+                    return Err(anyhow!(
+                        "Image size {} is not supported. Width and height must be multiples of {}.",
+                        self,
+                        multiple
+                    ));
+                }
+                Ok((width, height))
+            }
+            ImageSize::AspectRatio { .. } => {
+                let ratio = width as f64 / height.max(1) as f64;
+                let scaled_width = (default_area as f64 * ratio).sqrt();
+                let scaled_height = scaled_width / ratio;
+                Ok((
+                    round_to_multiple(scaled_width, multiple),
+                    round_to_multiple(scaled_height, multiple),
+                ))
+            }
+            ImageSize::Closest { .. } => Ok((
+                round_to_multiple(width as f64, multiple),
+                round_to_multiple(height as f64, multiple),
+            )),
+        }
+    }
+}
+
+/// How to make an image exactly the requested size when the provider generated a different size.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeMode {
+    /// Stretch the image to the requested size, ignoring its aspect ratio.
+    Stretch,
+    /// Scale the image to cover the requested size and cut off the edges that don't fit.
+    Crop,
+    /// Scale the image to fit inside the requested size and fill the rest with transparent pixels.
+    Pad,
+}
+
+impl Display for ResizeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeMode::Stretch => write!(f, "stretch"),
+            ResizeMode::Crop => write!(f, "crop"),
+            ResizeMode::Pad => write!(f, "pad"),
+        }
+    }
+}
+
+/// How different the aspect ratios of two sizes are.
+fn ratio_distance(a: (u32, u32), b: (u32, u32)) -> f64 {
+    let ratio_a = a.0 as f64 / a.1.max(1) as f64;
+    let ratio_b = b.0 as f64 / b.1.max(1) as f64;
+    (ratio_a.ln() - ratio_b.ln()).abs()
+}
+
+/// Round a dimension to the nearest multiple, never going below the multiple itself.
+fn round_to_multiple(value: f64, multiple: u32) -> u32 {
+    let multiple = multiple.max(1);
+    ((value / multiple as f64).round() as u32).max(1) * multiple
+}
+
+impl Display for ImageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageSize::Exact { width, height } => write!(f, "{}x{}", width, height),
+            ImageSize::AspectRatio { width, height } => write!(f, "{}:{}", width, height),
+            ImageSize::Closest { width, height } => write!(f, "~{}x{}", width, height),
+        }
+    }
+}
+
+impl FromStr for ImageSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_pair = |s: &str, separator: char| -> Result<(u32, u32)> {
+            let (width, height) = s
+                .split_once(separator)
+                .ok_or_else(|| anyhow!("Invalid image size: {:?}", s))?;
+            Ok((width.trim().parse()?, height.trim().parse()?))
+        };
+        let s = s.trim();
+        if let Some(closest) = s.strip_prefix('~') {
+            let (width, height) = parse_pair(closest, 'x')?;
+            Ok(ImageSize::Closest { width, height })
+        } else if s.contains(':') {
+            let (width, height) = parse_pair(s, ':')?;
+            if width == 0 || height == 0 {
+                return Err(anyhow!("Invalid aspect ratio: {:?}", s));
+            }
+            Ok(ImageSize::AspectRatio { width, height })
+        } else {
+            let (width, height) = parse_pair(s, 'x')?;
+            Ok(ImageSize::Exact { width, height })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DALL_E_3_SIZES: [(u32, u32); 3] = [(1024, 1024), (1792, 1024), (1024, 1792)];
+
+    #[test]
+    fn test_from_str() -> Result<()> {
+        assert_eq!(
+            "1024x768".parse::<ImageSize>()?,
+            ImageSize::Exact {
+                width: 1024,
+                height: 768
+            }
+        );
+        assert_eq!(
+            "~1000x700".parse::<ImageSize>()?,
+            ImageSize::Closest {
+                width: 1000,
+                height: 700
+            }
+        );
+        assert_eq!(
+            "16:9".parse::<ImageSize>()?,
+            ImageSize::AspectRatio {
+                width: 16,
+                height: 9
+            }
+        );
+        assert!("16/9".parse::<ImageSize>().is_err());
+        assert!("0:9".parse::<ImageSize>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_from() -> Result<()> {
+        let exact = ImageSize::Exact {
+            width: 1792,
+            height: 1024,
+        };
+        assert_eq!(exact.resolve_from(&DALL_E_3_SIZES)?, (1792, 1024));
+
+        let exact = ImageSize::Exact {
+            width: 512,
+            height: 512,
+        };
+        assert!(exact.resolve_from(&DALL_E_3_SIZES).is_err());
+
+        let aspect_ratio = ImageSize::AspectRatio {
+            width: 9,
+            height: 16,
+        };
+        assert_eq!(aspect_ratio.resolve_from(&DALL_E_3_SIZES)?, (1024, 1792));
+
+        let closest = ImageSize::Closest {
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(closest.resolve_from(&DALL_E_3_SIZES)?, (1792, 1024));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_to_multiple() -> Result<()> {
+        let exact = ImageSize::Exact {
+            width: 1000,
+            height: 1000,
+        };
+        assert_eq!(exact.resolve_to_multiple(8, 1024 * 1024)?, (1000, 1000));
+        assert!(exact.resolve_to_multiple(64, 1024 * 1024).is_err());
+
+        let closest = ImageSize::Closest {
+            width: 1000,
+            height: 750,
+        };
+        assert_eq!(closest.resolve_to_multiple(64, 1024 * 1024)?, (1024, 768));
+
+        let aspect_ratio = ImageSize::AspectRatio {
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(
+            aspect_ratio.resolve_to_multiple(64, 1024 * 1024)?,
+            (1024, 1024)
+        );
+
+        let aspect_ratio = ImageSize::AspectRatio {
+            width: 16,
+            height: 9,
+        };
+        assert_eq!(
+            aspect_ratio.resolve_to_multiple(64, 1024 * 1024)?,
+            (1344, 768)
+        );
+        Ok(())
+    }
+}
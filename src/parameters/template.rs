@@ -0,0 +1,416 @@
+//! Build prompts from templates with variables and wildcards.
+//!
+//! Templates contain variables like `{subject}` and wildcards like `__lighting__`.
+//! Variables are replaced with their values, and each combination of values gives a separate prompt.
+//! Wildcards are replaced with a random line from a wildcard file, as in Automatic1111's wildcards extension:
+//! `__lighting__` reads `lighting.txt` and `__colors/warm__` reads `colors/warm.txt` in the wildcard directories.
+//! Use `{{` and `}}` for literal braces.
+
+use crate::{
+    parameters::{prompt::ImagePrompt, text_to_image::TextToImageRequest},
+    providers::random_seed,
+};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+/// A template for the positive and negative prompts of a request.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Default)]
+pub struct PromptTemplate {
+    pub positive: String,
+    #[serde(default)]
+    pub negative: Option<String>,
+    /// The values of each variable. Variables with several values give a prompt for each value.
+    #[serde(default)]
+    pub variables: BTreeMap<String, Vec<String>>,
+    /// The directories searched for wildcard files, in order.
+    #[serde(default)]
+    pub wildcard_dirs: Vec<PathBuf>,
+    /// The seed used to pick lines from wildcard files, so the same prompts are built every time.
+    /// If not set, lines are picked at random.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// A piece of a parsed template.
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Text(&'a str),
+    /// A literal `{` or `}`.
+    Brace(char),
+    Variable(&'a str),
+    Wildcard(&'a str),
+}
+
+impl PromptTemplate {
+    pub fn new(positive: &str) -> Self {
+        PromptTemplate {
+            positive: positive.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_negative(mut self, negative: &str) -> Self {
+        self.negative = Some(negative.to_string());
+        self
+    }
+
+    /// Set a variable to a single value.
+    pub fn with_variable(self, name: &str, value: &str) -> Self {
+        self.with_values(name, [value])
+    }
+
+    /// Set a variable to several values, giving a prompt for each of them.
+    pub fn with_values<S: Into<String>>(
+        mut self,
+        name: &str,
+        values: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.variables.insert(
+            name.to_string(),
+            values.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    pub fn with_wildcard_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.wildcard_dirs.push(dir.into());
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Build a single prompt, using the first value of each variable.
+    pub fn render(&self) -> Result<ImagePrompt> {
+        let mut wildcards = WildcardFiles::new(&self.wildcard_dirs);
+        let mut rng = Rng::new(self.seed.unwrap_or_else(random_seed));
+        let values = self
+            .variables
+            .iter()
+            .filter_map(|(name, values)| Some((name.as_str(), values.first()?.as_str())))
+            .collect();
+        self.render_with(&values, &mut wildcards, &mut rng)
+    }
+
+    /// Build a prompt for every combination of the values of the variables used in the template.
+    /// Prompts are ordered with the last variable, in alphabetical order, changing fastest.
+    pub fn expand(&self) -> Result<Vec<ImagePrompt>> {
+        let used = self.used_variables()?;
+        let mut axes = Vec::new();
+        for name in &used {
+            match self.variables.get(*name) {
+                Some(values) if !values.is_empty() => axes.push((*name, values)),
+                _ => {
+                    return Err(anyhow!(
+                        "No value for variable in prompt template: {}",
+                        name
+                    ));
+                }
+            }
+        }
+        axes.sort_by_key(|(name, _)| *name);
+
+        let mut wildcards = WildcardFiles::new(&self.wildcard_dirs);
+        let mut rng = Rng::new(self.seed.unwrap_or_else(random_seed));
+        let mut prompts = Vec::new();
+        let mut indices = vec![0; axes.len()];
+        loop {
+            let values = axes
+                .iter()
+                .zip(&indices)
+                .map(|((name, values), index)| (*name, values[*index].as_str()))
+                .collect();
+            prompts.push(self.render_with(&values, &mut wildcards, &mut rng)?);
+
+            // Count up through the combinations, like an odometer.
+            let mut axis = axes.len();
+            loop {
+                if axis == 0 {
+                    return Ok(prompts);
+                }
+                axis -= 1;
+                indices[axis] += 1;
+                if indices[axis] < axes[axis].1.len() {
+                    break;
+                }
+                indices[axis] = 0;
+            }
+        }
+    }
+
+    /// Build a request for every prompt given by [`expand`](Self::expand), with the other parameters of `base`.
+    pub fn requests(&self, base: &TextToImageRequest) -> Result<Vec<TextToImageRequest>> {
+        Ok(self
+            .expand()?
+            .into_iter()
+            .map(|prompt| TextToImageRequest {
+                prompt,
+                ..base.clone()
+            })
+            .collect())
+    }
+
+    /// The names of the variables used in the positive and negative templates.
+    fn used_variables(&self) -> Result<Vec<&str>> {
+        let mut names = Vec::new();
+        for template in std::iter::once(&self.positive).chain(&self.negative) {
+            for segment in parse(template)? {
+                if let Segment::Variable(name) = segment
+                    && !names.contains(&name)
+                {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    fn render_with(
+        &self,
+        values: &HashMap<&str, &str>,
+        wildcards: &mut WildcardFiles,
+        rng: &mut Rng,
+    ) -> Result<ImagePrompt> {
+        let mut render = |template: &str| -> Result<String> {
+            let mut prompt = String::new();
+            for segment in parse(template)? {
+                match segment {
+                    Segment::Text(text) => prompt.push_str(text),
+                    Segment::Brace(brace) => prompt.push(brace),
+                    Segment::Variable(name) => {
+                        prompt.push_str(values.get(name).ok_or_else(|| {
+                            anyhow!("No value for variable in prompt template: {}", name)
+                        })?)
+                    }
+                    Segment::Wildcard(name) => {
+                        let lines = wildcards.lines(name)?;
+                        prompt.push_str(&lines[rng.below(lines.len())]);
+                    }
+                }
+            }
+            Ok(prompt)
+        };
+        Ok(ImagePrompt {
+            positive_prompt: Some(render(&self.positive)?),
+            negative_prompt: self.negative.as_deref().map(&mut render).transpose()?,
+        })
+    }
+}
+
+/// Split a template into text, variables and wildcards.
+fn parse(template: &str) -> Result<Vec<Segment<'_>>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while !rest.is_empty() {
+        let Some(start) = rest.find(['{', '}', '_']) else {
+            segments.push(Segment::Text(rest));
+            break;
+        };
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+            rest = &rest[start..];
+        }
+        if let Some(after) = rest.strip_prefix("{{") {
+            segments.push(Segment::Brace('{'));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("}}") {
+            segments.push(Segment::Brace('}'));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed variable in prompt template: {}", template))?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err(anyhow!("Empty variable in prompt template: {}", template));
+            }
+            segments.push(Segment::Variable(name));
+            rest = &after[end + 1..];
+        } else if rest.starts_with('}') {
+            return Err(anyhow!("Unmatched `}}` in prompt template: {}", template));
+        } else if let Some((name, after)) = wildcard(rest) {
+            segments.push(Segment::Wildcard(name));
+            rest = after;
+        } else {
+            segments.push(Segment::Text(&rest[..1]));
+            rest = &rest[1..];
+        }
+    }
+    Ok(segments)
+}
+
+/// Split a wildcard like `__name__` off the start of the text.
+fn wildcard(text: &str) -> Option<(&str, &str)> {
+    let after = text.strip_prefix("__")?;
+    let end = after.find("__")?;
+    let name = &after[..end];
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'));
+    valid.then(|| (name, &after[end + 2..]))
+}
+
+/// The lines of the wildcard files that have been read so far.
+struct WildcardFiles<'a> {
+    dirs: &'a [PathBuf],
+    lines: HashMap<String, Vec<String>>,
+}
+
+impl<'a> WildcardFiles<'a> {
+    fn new(dirs: &'a [PathBuf]) -> Self {
+        WildcardFiles {
+            dirs,
+            lines: HashMap::new(),
+        }
+    }
+
+    /// The lines of a wildcard file, without blank lines and `#` comments.
+    /// Names that could point outside of the wildcard directories, like `/etc/passwd`, are rejected.
+    fn lines(&mut self, name: &str) -> Result<&[String]> {
+        if name.split('/').any(|part| part.is_empty() || part == "..") {
+            return Err(anyhow!("Invalid wildcard name: {}", name));
+        }
+        if !self.lines.contains_key(name) {
+            let path = self
+                .dirs
+                .iter()
+                .map(|dir| dir.join(format!("{}.txt", name)))
+                .find(|path| path.is_file())
+                .ok_or_else(|| anyhow!("Wildcard file not found: {}.txt", name))?;
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read wildcard file {}", path.display()))?;
+            let lines: Vec<String> = contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect();
+            if lines.is_empty() {
+                return Err(anyhow!("Wildcard file is empty: {}", path.display()));
+            }
+            self.lines.insert(name.to_string(), lines);
+        }
+        Ok(&self.lines[name])
+    }
+}
+
+/// A small seeded random number generator (SplitMix64), so wildcards pick the same lines for the same seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to, but not including, `n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        assert_eq!(
+            parse("{style} portrait of {{me}}, __light_ing__ a_b")?,
+            vec![
+                Segment::Variable("style"),
+                Segment::Text(" portrait of "),
+                Segment::Brace('{'),
+                Segment::Text("me"),
+                Segment::Brace('}'),
+                Segment::Text(", "),
+                Segment::Wildcard("light_ing"),
+                Segment::Text(" a"),
+                Segment::Text("_"),
+                Segment::Text("b"),
+            ]
+        );
+        assert!(parse("{style").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand() -> Result<()> {
+        let template = PromptTemplate::new("{style} portrait of {subject}")
+            .with_negative("blurry {subject}")
+            .with_values("style", ["oil", "ink"])
+            .with_values("subject", ["a cat", "a dog"])
+            .with_values("unused", ["a", "b"]);
+        let prompts = template.expand()?;
+        let positive: Vec<_> = prompts
+            .iter()
+            .filter_map(|prompt| prompt.positive_prompt.as_deref())
+            .collect();
+        assert_eq!(
+            positive,
+            [
+                "oil portrait of a cat",
+                "oil portrait of a dog",
+                "ink portrait of a cat",
+                "ink portrait of a dog",
+            ]
+        );
+        assert_eq!(prompts[1].negative_prompt.as_deref(), Some("blurry a dog"));
+
+        let requests = template.requests(&TextToImageRequest {
+            num_batches: Some(2),
+            ..Default::default()
+        })?;
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[3].num_batches, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wildcards() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::create_dir(dir.path().join("colors"))?;
+        std::fs::write(
+            dir.path().join("colors/warm.txt"),
+            "# Warm colors\nred\n\norange\nyellow\n",
+        )?;
+        let template = PromptTemplate::new("a __colors/warm__ {thing}")
+            .with_variable("thing", "car")
+            .with_wildcard_dir(dir.path())
+            .with_seed(7);
+
+        let prompt = template.render()?.positive_prompt.unwrap_or_default();
+        let color = prompt
+            .strip_prefix("a ")
+            .and_then(|rest| rest.strip_suffix(" car"))
+            .unwrap_or_default();
+        assert!(["red", "orange", "yellow"].contains(&color));
+        // The same seed picks the same lines.
+        assert_eq!(template.render()?.positive_prompt, Some(prompt));
+
+        let missing = PromptTemplate::new("__missing__").with_wildcard_dir(dir.path());
+        assert!(missing.render().is_err());
+
+        for name in ["/etc/secrets", "colors//warm", "colors/"] {
+            let outside =
+                PromptTemplate::new(&format!("__{}__", name)).with_wildcard_dir(dir.path());
+            let error = outside.render().unwrap_err();
+            assert!(error.to_string().contains("Invalid wildcard name"));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
-use crate::parameters::prompt::ImagePrompt;
+use crate::parameters::{
+    prompt::ImagePrompt,
+    size::{ImageSize, ResizeMode},
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{path::PathBuf, str::FromStr};
 
 #[cfg(feature = "clap")]
-use clap::Args;
+use clap::{Args, ValueEnum};
 
 /// A request to generate an image from text.
 #[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone)]
@@ -19,11 +24,38 @@ pub struct TextToImageRequest {
     /// The width of the image in pixels.
     #[cfg_attr(feature = "clap", arg(long))]
     pub width: Option<u32>,
+    /// The size of the image, e.g. `1024x768`, `~1000x750` or `4:3`. Takes precedence over `width` and `height`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub size: Option<ImageSize>,
+    /// Resize the generated images to exactly the requested size if the provider generated a different size.
+    /// Requires the `image` feature.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub resize_mode: Option<ResizeMode>,
     /// The number of image batches to process. Most providers do not support multi-image batches, so for them this is equivalent to the number of images.
     #[cfg_attr(feature = "clap", arg(long))]
     pub num_batches: Option<u32>,
     #[cfg_attr(feature = "clap", clap(flatten))]
     pub extended: Option<TextToImageRequestExtendedParameters>,
+    #[cfg_attr(feature = "clap", clap(flatten))]
+    pub openai: Option<OpenAiRequestParameters>,
+    #[cfg_attr(feature = "clap", clap(flatten))]
+    pub stability: Option<StabilityRequestParameters>,
+    #[cfg_attr(feature = "clap", clap(flatten))]
+    pub comfyui: Option<ComfyUiRequestParameters>,
+    #[cfg_attr(feature = "clap", clap(flatten))]
+    pub imagen: Option<ImagenRequestParameters>,
+}
+
+impl TextToImageRequest {
+    /// The requested size of the image.
+    /// If `size` is not set, `width` and `height` are treated as the closest legal size.
+    pub fn image_size(&self) -> Option<ImageSize> {
+        match (self.size, self.width, self.height) {
+            (Some(size), _, _) => Some(size),
+            (None, Some(width), Some(height)) => Some(ImageSize::Closest { width, height }),
+            _ => None,
+        }
+    }
 }
 
 /// Additional parameters used by some providers.
@@ -49,3 +81,134 @@ pub struct TextToImageRequestExtendedParameters {
     #[cfg_attr(feature = "clap", arg(long))]
     pub seed: Option<u32>,
 }
+
+/// Additional parameters only used by the OpenAI provider.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone)]
+#[cfg_attr(feature = "clap", derive(Args))]
+pub struct OpenAiRequestParameters {
+    /// The quality of the image. `hd` is only supported by DALL-E 3.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub quality: Option<OpenAiImageQuality>,
+    /// The style of the image. Only supported by DALL-E 3.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub style: Option<OpenAiImageStyle>,
+    /// A unique identifier for the end user, which OpenAI uses to monitor and detect abuse.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub user: Option<String>,
+}
+
+/// The quality of an image generated by OpenAI.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum OpenAiImageQuality {
+    #[default]
+    Standard,
+    Hd,
+}
+
+/// The style of an image generated by OpenAI.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum OpenAiImageStyle {
+    /// Hyper-real and dramatic images.
+    #[default]
+    Vivid,
+    /// More natural, less hyper-real looking images.
+    Natural,
+}
+
+/// Additional parameters only used by the Stability AI provider.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone)]
+#[cfg_attr(feature = "clap", derive(Args))]
+pub struct StabilityRequestParameters {
+    /// The format of the generated image. SD3 models don't support `webp`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub output_format: Option<StabilityOutputFormat>,
+    /// Guide the image towards a particular style, e.g. `photographic` or `anime`. Only supported by Stable Image Core.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub style_preset: Option<String>,
+}
+
+/// The format of an image generated by Stability AI.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum StabilityOutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+/// Additional parameters only used by the Imagen provider.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone)]
+#[cfg_attr(feature = "clap", derive(Args))]
+pub struct ImagenRequestParameters {
+    /// Whether images of people may be generated.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub person_generation: Option<ImagenPersonGeneration>,
+    /// How strictly potentially harmful images are filtered out.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub safety_setting: Option<ImagenSafetySetting>,
+    /// Whether to add an invisible watermark to the images. Seeds are ignored unless this is `false`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub add_watermark: Option<bool>,
+}
+
+/// Whether Imagen may generate images of people.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum ImagenPersonGeneration {
+    DontAllow,
+    AllowAdult,
+    AllowAll,
+}
+
+/// How strictly Imagen filters out potentially harmful images.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum ImagenSafetySetting {
+    BlockLowAndAbove,
+    BlockMediumAndAbove,
+    BlockOnlyHigh,
+    BlockNone,
+}
+
+/// Additional parameters only used by the ComfyUI provider.
+#[derive(Debug, Deserialize, Default, Serialize, PartialEq, Clone)]
+#[cfg_attr(feature = "clap", derive(Args))]
+pub struct ComfyUiRequestParameters {
+    /// The path to a workflow template to use instead of the default text-to-image workflow.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub workflow: Option<PathBuf>,
+    /// Values for the variables bound in the workflow template, as `NAME=VALUE`.
+    #[cfg_attr(feature = "clap", arg(long = "variable"))]
+    #[serde(default)]
+    pub variables: Vec<WorkflowVariable>,
+}
+
+/// A named value that can be bound to an input of a workflow.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct WorkflowVariable {
+    pub name: String,
+    pub value: Value,
+}
+
+impl FromStr for WorkflowVariable {
+    type Err = anyhow::Error;
+
+    /// Parse a variable from `NAME=VALUE`. The value is parsed as JSON if possible, otherwise it is used as a string.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid variable {:?}. Expected NAME=VALUE.", s))?;
+        Ok(WorkflowVariable {
+            name: name.trim().to_string(),
+            value: serde_json::from_str(value).unwrap_or_else(|_| Value::from(value)),
+        })
+    }
+}
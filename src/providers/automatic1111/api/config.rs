@@ -1,6 +1,7 @@
 //! API endpoints for configuration management.
 
 use super::Automatic1111Provider;
+use crate::instrumentation;
 use anyhow::Result;
 use serde_json::Value;
 
@@ -9,7 +10,7 @@ impl Automatic1111Provider {
     pub async fn get_config(&self) -> Result<Value> {
         let endpoint = "/sdapi/v1/options";
         let url = format!("{}{}", self.base_url, endpoint);
-        let response = reqwest::get(&url).await?;
+        let response = instrumentation::get(&url).await?;
         // If the response is not successful, return an error.
         if !response.status().is_success() {
             return {
@@ -1,6 +1,7 @@
 //! Endpoints for interacting with the models available in the Stable Diffusion API.
 
 use super::Automatic1111Provider;
+use crate::instrumentation::{self, SendTraced};
 use anyhow::{Error, Result};
 use serde::{Deserialize, Serialize};
 
@@ -37,7 +38,7 @@ impl Automatic1111Provider {
     pub async fn get_models(&self) -> Result<Vec<StableDiffusionModel>> {
         let endpoint = "/sdapi/v1/sd-models";
         let url = format!("{}{}", self.base_url, endpoint);
-        let response = reqwest::get(url).await?;
+        let response = instrumentation::get(&url).await?;
         let models = response.text().await?;
         let models: Vec<StableDiffusionModel> = serde_json::from_str(&models)?;
         Ok(models)
@@ -50,7 +51,11 @@ impl Automatic1111Provider {
         let body = serde_json::json!({
             "sd_model_checkpoint": model_name,
         });
-        let response = reqwest::Client::new().post(url).json(&body).send().await?;
+        let response = reqwest::Client::new()
+            .post(url)
+            .json(&body)
+            .send_traced()
+            .await?;
 
         // Check that the response has a status code of 200.
         if !response.status().is_success() {
@@ -3,7 +3,9 @@
 use super::{Automatic1111Provider, txt2img::Txt2ImgRequestBody};
 use crate::{
     images::{LvmImage, LvmImageMetadata},
+    instrumentation::{self, SendTraced, event, in_span, metrics, warning},
     parameters::text_to_image::TextToImageRequest,
+    providers::middleware::inherit_headers,
 };
 use anyhow::{Result, anyhow};
 use base64::Engine;
@@ -65,7 +67,7 @@ struct QueueRequestBody {
 /// If the conversion fails, prints a warning and returns None.
 fn cfg_scale_to_number(f: f64) -> Option<Number> {
     Number::from_f64(f).or_else(|| {
-        eprintln!("Warning: Could not convert cfg_scale to Number.");
+        warning!(cfg_scale = f, "Could not convert cfg_scale to Number.");
         None
     })
 }
@@ -92,6 +94,12 @@ impl From<TextToImageRequest> for QueueRequestBody {
     }
 }
 
+/// The tasks waiting in the queue.
+#[derive(Debug, Deserialize)]
+struct QueueResponse {
+    total_pending_tasks: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct TaskStatusParams {
     checkpoint: Option<String>,
@@ -146,7 +154,7 @@ impl Automatic1111Provider {
         let url = format!("{}{}", self.base_url, endpoint);
         let body = serde_json::to_string(request_body)?;
         let client = reqwest::Client::new();
-        let response = client.post(url).body(body).send().await?;
+        let response = client.post(url).body(body).send_traced().await?;
         let response_text = response.text().await?;
         let response: QueueTaskResponse = serde_json::from_str(&response_text)?;
         Ok(response.task_id)
@@ -156,7 +164,7 @@ impl Automatic1111Provider {
     async fn get_task_status(&self, task_id: &TaskId) -> Result<TaskStatus> {
         let endpoint = format!("/agent-scheduler/v1/task/{}", task_id);
         let url = format!("{}{}", self.base_url, endpoint);
-        let response = reqwest::get(url).await?;
+        let response = instrumentation::get(&url).await?;
         let response_text = response.text().await?;
         let response: TaskStatusResponse = serde_json::from_str(&response_text)?;
         // The status is in the "msg" field of the response.
@@ -176,7 +184,7 @@ impl Automatic1111Provider {
     async fn get_task_results(&self, task_id: &str) -> Result<Vec<Vec<u8>>> {
         let endpoint = format!("/agent-scheduler/v1/task/{}/results", task_id);
         let url = format!("{}{}", self.base_url, endpoint);
-        let response = reqwest::get(url).await?;
+        let response = instrumentation::get(&url).await?;
         let response_text = response.text().await?;
         let results: TaskResults = serde_json::from_str(&response_text)?;
         let images: Vec<Vec<u8>> = results
@@ -191,14 +199,29 @@ impl Automatic1111Provider {
     }
 
     /// Poll the task until it is complete, returning the base64-encoded images.
-    async fn poll_task(&self, task_id: &TaskId) -> Result<Vec<LvmImage>> {
+    /// `provider` is the name its queue wait is recorded under.
+    async fn poll_task(&self, task_id: &TaskId, provider: &str) -> Result<Vec<LvmImage>> {
         let timeout = std::time::Duration::from_secs(300);
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        let queued_at = std::time::Instant::now();
+        let mut started = false;
 
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    let status = self.get_task_status(task_id).await?;
+                    let status = in_span!(
+                        async {
+                            let status = self.get_task_status(task_id).await?;
+                            event!(debug, status = ?status, "Polled task");
+                            anyhow::Ok(status)
+                        },
+                        tracing::debug_span!("poll_task", task_id = %task_id)
+                    )
+                    .await?;
+                    if !started && !matches!(status, TaskStatus::Pending) {
+                        started = true;
+                        metrics::record_queue_wait(provider, queued_at.elapsed());
+                    }
                     match status {
                         TaskStatus::Done => {
                             let images: Vec<LvmImage> = self.get_task_results(task_id).await?
@@ -206,6 +229,7 @@ impl Automatic1111Provider {
                                 .map(|image| {
                                     let metadata = Some(LvmImageMetadata {
                                         generation_params: serde_json::to_string(&Txt2ImgRequestBody::default()).ok(),
+                                        ..Default::default()
                                     });
                                     LvmImage {
                                         data: image.clone(),
@@ -228,6 +252,34 @@ impl Automatic1111Provider {
         }
     }
 
+    /// Get the number of tasks waiting in the queue.
+    /// Fails if the instance can't be reached within `timeout`, so this also checks whether the instance is up.
+    pub async fn queue_length(&self, timeout: std::time::Duration) -> Result<u32> {
+        let url = format!("{}/agent-scheduler/v1/queue", self.base_url);
+        let response = reqwest::Client::new()
+            .get(url)
+            .timeout(timeout)
+            .send_traced()
+            .await?
+            .error_for_status()?;
+        let response: QueueResponse = response.json().await?;
+        Ok(response.total_pending_tasks)
+    }
+
+    /// Send a single txt2img task to the queue and wait for its images.
+    /// Unlike `queue_txt2img`, failing to start the task is returned as an error.
+    /// `provider` is the name its queue wait is recorded under, e.g. that of the pool sending it.
+    pub async fn run_txt2img_task(
+        &self,
+        request: TextToImageRequest,
+        provider: &str,
+    ) -> Result<Vec<LvmImage>> {
+        let task_id = self
+            .start_image_generation_task(&QueueRequestBody::from(request))
+            .await?;
+        self.poll_task(&task_id, provider).await
+    }
+
     /// Send txt2img tasks to the queue for each num_batches.
     pub async fn queue_txt2img(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
         // If num_batches is None, default to 1.
@@ -241,11 +293,11 @@ impl Automatic1111Provider {
         let handles = (0..num_batches).map(|_| {
             let provider_config = std::sync::Arc::clone(&provider_config);
             let request_clone = request.clone();
-            tokio::spawn(async move {
+            tokio::spawn(inherit_headers(async move {
                 provider_config
                     .start_image_generation_task(&request_clone)
                     .await
-            })
+            }))
         });
         let mut task_ids: Vec<Result<TaskId>> = Vec::new();
         for handle in handles {
@@ -257,11 +309,11 @@ impl Automatic1111Provider {
         for task_id in task_ids {
             match task_id {
                 Ok(task_id) => {
-                    let task_images = self.poll_task(&task_id).await?;
+                    let task_images = self.poll_task(&task_id, "automatic1111").await?;
                     images.extend(task_images);
                 }
                 Err(e) => {
-                    eprintln!("Error starting task: {}", e);
+                    warning!("Error starting task: {}", e);
                 }
             }
         }
@@ -292,7 +344,7 @@ mod tests {
         let provider = Automatic1111Provider::default();
         let request_body = QueueRequestBody::default();
         let task_id = provider.start_image_generation_task(&request_body).await?;
-        let image = provider.poll_task(&task_id).await?;
+        let image = provider.poll_task(&task_id, "automatic1111").await?;
         assert!(!image.first().unwrap().data.is_empty());
         Ok(())
     }
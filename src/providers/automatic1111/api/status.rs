@@ -1,13 +1,13 @@
 //! Status API for Stable Diffusion XL.
 
 use super::Automatic1111Provider;
+use crate::instrumentation;
 use anyhow::Result;
-use reqwest::get;
 
 impl Automatic1111Provider {
     /// Send a GET request to `/sdapi/v1/status` to check if the local Stable Diffusion instance is up.
     pub async fn is_up(&self) -> Result<bool> {
-        let response = get(&self.base_url).await?;
+        let response = instrumentation::get(&self.base_url).await?;
         Ok(response.status().is_success())
     }
 }
@@ -3,7 +3,7 @@
 //! Generally don't use this: use the `queue` module instead.
 
 //use super::Automatic1111Provider;
-//use crate::{LvmImage, images::LvmImageMetadata};
+//use crate::{LvmImage, images::LvmImageMetadata, instrumentation::SendTraced};
 //use anyhow::Result;
 use serde::{Deserialize, Serialize};
 //use serde_json::Value;
@@ -44,7 +44,7 @@ impl Automatic1111Provider {
         let url = format!("{}{}", self.base_url, endpoint);
         let body = serde_json::to_string(request)?;
         let client = reqwest::Client::new();
-        let response = client.post(url).body(body).send().await?;
+        let response = client.post(url).body(body).send_traced().await?;
         let response: Value = serde_json::from_str(&response.text().await?)?;
         let images: Vec<LvmImage> = response["images"]
             .as_array()
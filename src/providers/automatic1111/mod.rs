@@ -1,7 +1,8 @@
 pub mod api;
+pub mod pool;
 
 use crate::{
-    LvmImage, parameters::provider::ProviderConfiguration,
+    LvmImage, parameters::provider::ProviderConfiguration, parameters::size::ImageSize,
     parameters::text_to_image::TextToImageRequest, traits::TextToImageProvider,
 };
 use anyhow::Result;
@@ -9,6 +10,22 @@ use async_trait::async_trait;
 
 const DEFAULT_BASE_URL: &str = "http://localhost:7860";
 
+/// Stable Diffusion works in 8x8 pixel latent blocks, so exact sizes must be a multiple of this.
+const SIZE_MULTIPLE: u32 = 8;
+/// Sizes that don't need to be exact are rounded to this, which most models are trained on.
+const PREFERRED_SIZE_MULTIPLE: u32 = 64;
+/// The number of pixels to aim for when only an aspect ratio is given.
+const DEFAULT_AREA: u32 = 1024 * 1024;
+
+/// Pick the size closest to the requested size that Stable Diffusion supports.
+/// Used by every Stable Diffusion backend, like ComfyUI and InvokeAI, since they run the same models.
+pub fn resolve_size(size: &ImageSize) -> Result<(u32, u32)> {
+    match size {
+        ImageSize::Exact { .. } => size.resolve_to_multiple(SIZE_MULTIPLE, DEFAULT_AREA),
+        _ => size.resolve_to_multiple(PREFERRED_SIZE_MULTIPLE, DEFAULT_AREA),
+    }
+}
+
 /// A provider for generating images with the Automatic1111 instance.
 /// These are the fields common to all requests to the Automatic1111 provider.
 /// Most of these fields are optional since Automatic1111 will fill in the missing fields with default values set on the server.
@@ -45,4 +62,29 @@ impl TextToImageProvider for Automatic1111Provider {
     async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
         self.queue_txt2img(request).await
     }
+
+    // `max_images_per_request` is left unset, since one task is queued per batch.
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_size() -> Result<()> {
+        let exact = ImageSize::Exact {
+            width: 1000,
+            height: 1000,
+        };
+        assert_eq!(resolve_size(&exact)?, (1000, 1000));
+        let closest = ImageSize::Closest {
+            width: 1000,
+            height: 1000,
+        };
+        assert_eq!(resolve_size(&closest)?, (1024, 1024));
+        Ok(())
+    }
 }
@@ -0,0 +1,429 @@
+//! Spread requests across several Automatic1111 instances.
+
+use super::Automatic1111Provider;
+use crate::{
+    errors::ErrorKind,
+    images::LvmImage,
+    instrumentation::metrics,
+    parameters::{
+        provider::ProviderConfiguration, size::ImageSize, text_to_image::TextToImageRequest,
+    },
+    providers::{
+        batch::BatchResults, index::DEFAULT_MAX_CONCURRENT_REQUESTS, middleware::inherit_headers,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// How long to wait for an instance to report its queue before treating it as down.
+const HEALTH_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How batches are distributed between the instances in a pool.
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancingStrategy {
+    /// Send each batch to the instance with the fewest tasks waiting in its agent-scheduler queue.
+    #[default]
+    LeastQueued,
+    /// Send batches to each instance in turn.
+    RoundRobin,
+}
+
+/// Configuration for a pool of Automatic1111 instances.
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct Automatic1111PoolConfiguration {
+    #[serde(flatten)]
+    pub provider: ProviderConfiguration,
+    /// The URLs of the instances in the pool, in addition to `base_url` if it is set.
+    #[serde(default)]
+    pub base_urls: Vec<String>,
+    #[serde(default)]
+    pub strategy: LoadBalancingStrategy,
+}
+
+/// A provider that sends each batch of a request to one of several Automatic1111 instances.
+/// Instances that are down are skipped, and batches sent to an instance that goes down are retried on another one.
+#[derive(Debug)]
+pub struct Automatic1111PoolProvider {
+    pub nodes: Vec<Automatic1111Provider>,
+    pub strategy: LoadBalancingStrategy,
+    /// The maximum number of batches running at the same time across the pool.
+    pub max_concurrent_requests: u32,
+    /// The node the next round-robin batch is sent to.
+    next: AtomicUsize,
+}
+
+impl From<&Automatic1111PoolConfiguration> for Automatic1111PoolProvider {
+    fn from(config: &Automatic1111PoolConfiguration) -> Self {
+        Automatic1111PoolProvider {
+            nodes: config
+                .provider
+                .base_url
+                .iter()
+                .chain(&config.base_urls)
+                .map(|base_url| Automatic1111Provider {
+                    base_url: base_url.clone(),
+                })
+                .collect(),
+            strategy: config.strategy,
+            max_concurrent_requests: config
+                .provider
+                .max_concurrent_requests
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS)
+                .max(1),
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Automatic1111PoolProvider {
+    /// Get the queue length of every node, or `None` for nodes that are down.
+    async fn queue_lengths(&self) -> Vec<Option<u32>> {
+        let mut handles = Vec::new();
+        for node in &self.nodes {
+            let node = node.clone();
+            handles.push(tokio::spawn(inherit_headers(async move {
+                node.queue_length(HEALTH_CHECK_TIMEOUT).await
+            })));
+        }
+        let mut queue_lengths = Vec::new();
+        for handle in handles {
+            queue_lengths.push(handle.await.ok().and_then(|length| length.ok()));
+        }
+        queue_lengths
+    }
+
+    /// Pick the node to send the next batch to from the nodes that are up.
+    /// Nodes picked by the least-queued strategy count the batch towards their queue length.
+    fn pick(&self, queue_lengths: &mut [Option<u32>]) -> Option<usize> {
+        let index = match self.strategy {
+            LoadBalancingStrategy::LeastQueued => queue_lengths
+                .iter()
+                .enumerate()
+                .filter_map(|(index, length)| length.map(|length| (index, length)))
+                .min_by_key(|(_, length)| *length)
+                .map(|(index, _)| index)?,
+            LoadBalancingStrategy::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..queue_lengths.len())
+                    .map(|offset| (start + offset) % queue_lengths.len())
+                    .find(|index| queue_lengths[*index].is_some())?
+            }
+        };
+        if let Some(length) = &mut queue_lengths[index] {
+            *length += 1;
+        }
+        Some(index)
+    }
+
+    /// Send each batch of the request to a node as its own task, with at most `max_concurrent_requests` running at once.
+    /// Batches that fail are returned as errors alongside the images of the others.
+    /// The tasks are aborted if the returned future is dropped.
+    pub async fn text_to_image_batched(&self, request: TextToImageRequest) -> BatchResults {
+        let mut queue_lengths = self.queue_lengths().await;
+        if queue_lengths.iter().all(Option::is_none) {
+            return BatchResults {
+                images: Vec::new(),
+                errors: vec![anyhow!("No Automatic1111 instances in the pool are up.")],
+            };
+        }
+        let down = Arc::new(Mutex::new(
+            queue_lengths.iter().map(Option::is_none).collect(),
+        ));
+        let nodes = Arc::new(self.nodes.clone());
+        let semaphore = Arc::new(Semaphore::new(self.max_concurrent_requests as usize));
+
+        let mut tasks = JoinSet::new();
+        for _ in 0..request.num_batches.unwrap_or(1).max(1) {
+            let Some(first) = self.pick(&mut queue_lengths) else {
+                break;
+            };
+            // Retry on the other nodes in order, after the one picked for the batch.
+            let order = (0..nodes.len())
+                .map(|offset| (first + offset) % nodes.len())
+                .collect();
+            let request = TextToImageRequest {
+                num_batches: Some(1),
+                ..request.clone()
+            };
+            let (nodes, down, semaphore) = (
+                Arc::clone(&nodes),
+                Arc::clone(&down),
+                Arc::clone(&semaphore),
+            );
+            tasks.spawn(inherit_headers(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                run_batch(nodes, down, order, request).await
+            }));
+        }
+
+        let mut results = BatchResults::default();
+        while let Some(result) = tasks.join_next().await {
+            match result.map_err(anyhow::Error::from).and_then(|r| r) {
+                Ok(images) => results.images.extend(images),
+                Err(error) => results.errors.push(error),
+            }
+        }
+        results
+    }
+}
+
+/// Run a batch on the first node in `order` that is up.
+/// If a node can't be reached, it is marked as down and the batch is retried on the next one.
+/// Other errors, including timeouts, aren't retried, since the node may still be generating the batch.
+async fn run_batch(
+    nodes: Arc<Vec<Automatic1111Provider>>,
+    down: Arc<Mutex<Vec<bool>>>,
+    order: Vec<usize>,
+    request: TextToImageRequest,
+) -> Result<Vec<LvmImage>> {
+    let mut last_error = None;
+    for index in order {
+        let is_down = down.lock().unwrap_or_else(|p| p.into_inner())[index];
+        if is_down {
+            continue;
+        }
+        if last_error.is_some() {
+            metrics::record_retry("automatic1111-pool");
+        }
+        match nodes[index]
+            .run_txt2img_task(request.clone(), "automatic1111-pool")
+            .await
+        {
+            Ok(images) => return Ok(images),
+            Err(error) if ErrorKind::of(&error) == ErrorKind::Connection => {
+                down.lock().unwrap_or_else(|p| p.into_inner())[index] = true;
+                last_error = Some(error);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow!("No Automatic1111 instances in the pool are up.")))
+}
+
+#[async_trait]
+impl TextToImageProvider for Automatic1111PoolProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        super::resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LvmProviders;
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    /// Start an instance with `queue_length` tasks waiting, which completes tasks with an image named after `task_id`.
+    async fn node(queue_length: u32, task_id: &str) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/agent-scheduler/v1/queue"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "current_task_id": null,
+                "pending_tasks": [],
+                "total_pending_tasks": queue_length,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/agent-scheduler/v1/queue/txt2img"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "task_id": task_id })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/agent-scheduler/v1/task/{}", task_id)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "data": {
+                    "id": task_id,
+                    "api_task_id": null,
+                    "api_task_callback": null,
+                    "name": null,
+                    "type": "txt2img",
+                    "status": "done",
+                    "params": {},
+                    "priority": 0,
+                    "position": null,
+                    "result": null,
+                    "bookmarked": null,
+                    "created_at": "",
+                    "updated_at": "",
+                },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/agent-scheduler/v1/task/{}/results",
+                task_id
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "data": [{ "image": base64_image(task_id), "infotext": "" }],
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn base64_image(name: &str) -> String {
+        use base64::Engine;
+        format!(
+            "data:image/png;base64,{}",
+            base64::prelude::BASE64_STANDARD.encode(name)
+        )
+    }
+
+    /// The URL of an instance that is down.
+    fn down_node() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    }
+
+    fn pool(base_urls: Vec<String>, strategy: LoadBalancingStrategy) -> Automatic1111PoolProvider {
+        Automatic1111PoolProvider::from(&Automatic1111PoolConfiguration {
+            base_urls,
+            strategy,
+            ..Default::default()
+        })
+    }
+
+    fn request(num_batches: u32) -> TextToImageRequest {
+        TextToImageRequest {
+            num_batches: Some(num_batches),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pick() {
+        let provider = pool(
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            LoadBalancingStrategy::LeastQueued,
+        );
+        let mut queue_lengths = vec![Some(2), None, Some(0)];
+        let picks: Vec<_> = (0..4).map(|_| provider.pick(&mut queue_lengths)).collect();
+        assert_eq!(picks, vec![Some(2), Some(2), Some(0), Some(2)]);
+
+        let provider = pool(
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            LoadBalancingStrategy::RoundRobin,
+        );
+        let mut queue_lengths = vec![Some(2), None, Some(0)];
+        let picks: Vec<_> = (0..3).map(|_| provider.pick(&mut queue_lengths)).collect();
+        assert_eq!(picks, vec![Some(0), Some(2), Some(2)]);
+        assert_eq!(provider.pick(&mut [None, None]), None);
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_least_queued() -> Result<()> {
+        let busy = node(5, "busy").await;
+        let idle = node(0, "idle").await;
+        let provider = pool(
+            vec![down_node(), busy.uri(), idle.uri()],
+            LoadBalancingStrategy::LeastQueued,
+        );
+        let images = provider.text_to_image(request(2)).await?;
+        assert_eq!(images.len(), 2);
+        for image in &images {
+            assert_eq!(image.data, b"idle");
+        }
+        Ok(())
+    }
+
+    /// Start an instance that is up but rejects every task.
+    async fn rejecting_node() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/agent-scheduler/v1/queue"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "current_task_id": null,
+                "pending_tasks": [],
+                "total_pending_tasks": 0,
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/agent-scheduler/v1/queue/txt2img"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    /// A round-robin pool of the instances, running one batch at a time.
+    fn round_robin(base_urls: Vec<String>) -> Automatic1111PoolConfiguration {
+        Automatic1111PoolConfiguration {
+            provider: ProviderConfiguration {
+                max_concurrent_requests: Some(1),
+                ..Default::default()
+            },
+            base_urls,
+            strategy: LoadBalancingStrategy::RoundRobin,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_batched_partial_failure() {
+        let up = node(0, "up").await;
+        let rejecting = rejecting_node().await;
+        let provider =
+            Automatic1111PoolProvider::from(&round_robin(vec![up.uri(), rejecting.uri()]));
+        let results = provider.text_to_image_batched(request(4)).await;
+        assert_eq!(results.images.len(), 2);
+        assert_eq!(results.errors.len(), 2);
+
+        let results = provider.text_to_image_batched(request(0)).await;
+        assert_eq!(results.images.len() + results.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lvm_providers_partial_failure() {
+        let up = node(0, "up").await;
+        let rejecting = rejecting_node().await;
+        let provider =
+            LvmProviders::Automatic1111Pool(round_robin(vec![up.uri(), rejecting.uri()]));
+        let results = provider.text_to_image_batched(request(2)).await;
+        assert_eq!(results.images.len(), 1);
+        assert_eq!(results.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_batch_retries_on_another_node() -> Result<()> {
+        let up = node(0, "up").await;
+        let nodes = vec![
+            Automatic1111Provider {
+                base_url: down_node(),
+            },
+            Automatic1111Provider { base_url: up.uri() },
+        ];
+        let down = Arc::new(Mutex::new(vec![false, false]));
+        let images = run_batch(Arc::new(nodes), down.clone(), vec![0, 1], request(1)).await?;
+        assert_eq!(images[0].data, b"up");
+        assert_eq!(*down.lock().unwrap(), vec![true, false]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_all_down() {
+        let provider = pool(vec![down_node()], LoadBalancingStrategy::RoundRobin);
+        let error = provider.text_to_image(request(1)).await.unwrap_err();
+        assert!(error.to_string().contains("No Automatic1111 instances"));
+    }
+}
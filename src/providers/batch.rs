@@ -0,0 +1,74 @@
+//! Split requests into sub-requests that a provider can handle.
+
+use crate::{images::LvmImage, instrumentation::warning};
+use anyhow::Result;
+
+/// The results of a request that was split into several sub-requests.
+/// Each sub-request can fail on its own, so a request can partially succeed.
+#[derive(Debug, Default)]
+pub struct BatchResults {
+    /// The images generated by the sub-requests that succeeded.
+    pub images: Vec<LvmImage>,
+    /// The errors returned by the sub-requests that failed.
+    pub errors: Vec<anyhow::Error>,
+}
+
+impl BatchResults {
+    /// Return the images if any sub-request succeeded, otherwise the first error.
+    /// Errors from failed sub-requests are printed when some images were still generated.
+    pub fn into_result(self) -> Result<Vec<LvmImage>> {
+        if self.images.is_empty() {
+            if let Some(error) = self.errors.into_iter().next() {
+                return Err(error);
+            }
+        } else {
+            for error in &self.errors {
+                warning!("Error generating images: {}", error);
+            }
+        }
+        Ok(self.images)
+    }
+}
+
+/// Split `num_images` into batches of at most `max_per_batch` images.
+pub(crate) fn split_batches(num_images: u32, max_per_batch: u32) -> Vec<u32> {
+    let max_per_batch = max_per_batch.max(1);
+    let mut batches = vec![max_per_batch; (num_images / max_per_batch) as usize];
+    let remainder = num_images % max_per_batch;
+    if remainder > 0 {
+        batches.push(remainder);
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_batches() {
+        assert_eq!(split_batches(3, 1), vec![1, 1, 1]);
+        assert_eq!(split_batches(25, 10), vec![10, 10, 5]);
+        assert_eq!(split_batches(10, 10), vec![10]);
+        assert_eq!(split_batches(4, 0), vec![1, 1, 1, 1]);
+        assert!(split_batches(0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_into_result() {
+        let results = BatchResults {
+            images: vec![LvmImage {
+                data: vec![1],
+                metadata: None,
+            }],
+            errors: vec![anyhow::anyhow!("Failed")],
+        };
+        assert_eq!(results.into_result().unwrap().len(), 1);
+
+        let results = BatchResults {
+            images: Vec::new(),
+            errors: vec![anyhow::anyhow!("Failed")],
+        };
+        assert!(results.into_result().is_err());
+    }
+}
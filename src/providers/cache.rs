@@ -0,0 +1,442 @@
+//! Reuse the images generated for identical requests.
+//!
+//! Requests are keyed by a hash of the canonical JSON of the provider and the request, which includes the model.
+//! Only requests with a seed are cached by default, since requests without one generate different images each time.
+//! Requests to custom providers aren't cached, since they can't be told apart; register them under a name instead.
+
+use crate::{
+    images::{LvmImage, LvmImageMetadata},
+    instrumentation::warning,
+    parameters::text_to_image::TextToImageRequest,
+    providers::{batch::BatchResults, custom::registered_provider, index::LvmProviders},
+    traits::TextToImageProvider,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Where cached images are stored.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackend {
+    /// Keep the images of up to `capacity` requests in memory, evicting the least recently used.
+    /// The cache is shared by clones of the provider, but not kept between runs.
+    Memory { capacity: usize },
+    /// Keep the images of each request in a JSON file in a directory.
+    Disk { path: PathBuf },
+}
+
+/// A cached request's images.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct CacheEntry {
+    /// When the entry was created, in seconds since the Unix epoch.
+    created_at: u64,
+    images: Vec<CachedImage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct CachedImage {
+    /// The image's data, encoded in base64 so it can be stored as JSON.
+    data: String,
+    metadata: Option<LvmImageMetadata>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryCache {
+    /// Entries by key, with the tick they were last used at.
+    entries: HashMap<String, (CacheEntry, u64)>,
+    tick: u64,
+}
+
+/// Wraps a provider, returning the images it generated for an identical request instead of sending the request again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedProvider {
+    pub provider: Box<LvmProviders>,
+    pub backend: CacheBackend,
+    /// How long cached images are used for, in seconds. They are used forever if this is `None`.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Send every request to the provider, replacing any cached images with the new ones.
+    #[serde(default)]
+    pub bypass: bool,
+    /// Also cache requests without a seed.
+    #[serde(default)]
+    pub unseeded: bool,
+    #[serde(skip)]
+    memory: Arc<Mutex<MemoryCache>>,
+}
+
+impl PartialEq for CachedProvider {
+    /// Cached providers are equal if they are configured the same, whatever they have cached.
+    fn eq(&self, other: &Self) -> bool {
+        self.provider == other.provider
+            && self.backend == other.backend
+            && self.ttl_secs == other.ttl_secs
+            && self.bypass == other.bypass
+            && self.unseeded == other.unseeded
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// What identifies a provider in cache keys, or `None` if it can't be identified.
+/// Providers are identified by their serialized configuration, and registered providers by their name and configuration.
+/// Custom providers and middleware can't be serialized, nor can providers that wrap them.
+fn identity(provider: &LvmProviders) -> Option<serde_json::Value> {
+    if let LvmProviders::Registered(name) = provider {
+        let configuration = registered_provider(name)?.configuration;
+        return Some(json!({ "registered": name, "configuration": configuration }));
+    }
+    serde_json::to_value(provider).ok()
+}
+
+/// A 64-bit FNV-1a hash, which unlike the standard library's hasher is the same between Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl CachedProvider {
+    pub fn new(provider: LvmProviders, backend: CacheBackend) -> Self {
+        CachedProvider {
+            provider: Box::new(provider),
+            backend,
+            ttl_secs: None,
+            bypass: false,
+            unseeded: false,
+            memory: Arc::default(),
+        }
+    }
+
+    pub fn with_ttl_secs(mut self, ttl_secs: u64) -> Self {
+        self.ttl_secs = Some(ttl_secs);
+        self
+    }
+
+    pub fn with_bypass(mut self, bypass: bool) -> Self {
+        self.bypass = bypass;
+        self
+    }
+
+    /// The key a request is cached under, or `None` if it isn't cached.
+    pub fn key(&self, request: &TextToImageRequest) -> Option<String> {
+        let seeded = request
+            .extended
+            .as_ref()
+            .is_some_and(|extended| extended.seed.is_some());
+        if !seeded && !self.unseeded {
+            return None;
+        }
+        let provider = identity(&self.provider)?;
+        // Objects are serialized with their keys sorted, so equal requests always have the same JSON.
+        let canonical = json!({ "provider": provider, "request": request }).to_string();
+        Some(format!("{:016x}", fnv1a(canonical.as_bytes())))
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry) -> bool {
+        self.ttl_secs
+            .is_none_or(|ttl_secs| now().saturating_sub(entry.created_at) < ttl_secs)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let entry = match &self.backend {
+            CacheBackend::Memory { .. } => {
+                let mut memory = self.memory.lock().unwrap_or_else(|p| p.into_inner());
+                memory.tick += 1;
+                let tick = memory.tick;
+                memory.entries.get_mut(key).map(|(entry, last_used)| {
+                    *last_used = tick;
+                    entry.clone()
+                })
+            }
+            CacheBackend::Disk { path } => {
+                let contents = match tokio::fs::read(path.join(format!("{}.json", key))).await {
+                    Ok(contents) => contents,
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(error) => return Err(error.into()),
+                };
+                Some(serde_json::from_slice(&contents)?)
+            }
+        };
+        Ok(entry.filter(|entry| self.is_fresh(entry)))
+    }
+
+    async fn put(&self, key: String, entry: CacheEntry) -> Result<()> {
+        match &self.backend {
+            CacheBackend::Memory { capacity } => {
+                let mut memory = self.memory.lock().unwrap_or_else(|p| p.into_inner());
+                memory.tick += 1;
+                let tick = memory.tick;
+                memory.entries.insert(key, (entry, tick));
+                while memory.entries.len() > *capacity {
+                    let Some(least_recently_used) = memory
+                        .entries
+                        .iter()
+                        .min_by_key(|(_, (_, last_used))| *last_used)
+                        .map(|(key, _)| key.clone())
+                    else {
+                        break;
+                    };
+                    memory.entries.remove(&least_recently_used);
+                }
+            }
+            CacheBackend::Disk { path } => {
+                tokio::fs::create_dir_all(path).await?;
+                tokio::fs::write(
+                    path.join(format!("{}.json", key)),
+                    serde_json::to_vec(&entry)?,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the cached images for the request if there are any, otherwise send it to the provider.
+    /// Only requests that succeed completely are cached.
+    pub fn text_to_image_batched(
+        &self,
+        request: TextToImageRequest,
+    ) -> Pin<Box<dyn Future<Output = BatchResults> + Send + '_>> {
+        // Boxed, since the provider can itself be cached.
+        Box::pin(async move {
+            let Some(key) = self.key(&request) else {
+                return self.provider.text_to_image_batched(request).await;
+            };
+            if !self.bypass {
+                match self.get(&key).await {
+                    Ok(Some(entry)) => {
+                        let images: Result<Vec<LvmImage>> = entry
+                            .images
+                            .into_iter()
+                            .map(|image| {
+                                Ok(LvmImage {
+                                    data: base64::prelude::BASE64_STANDARD.decode(image.data)?,
+                                    metadata: image.metadata,
+                                })
+                            })
+                            .collect();
+                        match images {
+                            Ok(images) => {
+                                return BatchResults {
+                                    images,
+                                    errors: Vec::new(),
+                                };
+                            }
+                            Err(error) => warning!("Error reading cached images: {}", error),
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(error) => warning!("Error reading cached images: {}", error),
+                }
+            }
+
+            let results = self.provider.text_to_image_batched(request).await;
+            if results.errors.is_empty() && !results.images.is_empty() {
+                let entry = CacheEntry {
+                    created_at: now(),
+                    images: results
+                        .images
+                        .iter()
+                        .map(|image| CachedImage {
+                            data: base64::prelude::BASE64_STANDARD.encode(&image.data),
+                            metadata: image.metadata.clone(),
+                        })
+                        .collect(),
+                };
+                if let Err(error) = self.put(key, entry).await {
+                    warning!("Error caching images: {}", error);
+                }
+            }
+            results
+        })
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for CachedProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parameters::text_to_image::TextToImageRequestExtendedParameters,
+        providers::custom::{CustomProvider, register_provider},
+    };
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tempfile::tempdir;
+
+    /// Generates an image containing the request's prompt, counting how many requests it was sent.
+    #[derive(Default)]
+    struct CountingProvider {
+        requests: AtomicU32,
+    }
+
+    #[async_trait]
+    impl TextToImageProvider for CountingProvider {
+        async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![LvmImage {
+                data: request
+                    .prompt
+                    .positive_prompt
+                    .unwrap_or_default()
+                    .into_bytes(),
+                metadata: None,
+            }])
+        }
+    }
+
+    /// A counting provider registered under `name`, since only providers that can be identified are cached.
+    fn counting(name: &str) -> (Arc<CountingProvider>, LvmProviders) {
+        let provider = Arc::new(CountingProvider::default());
+        register_provider(
+            name,
+            CustomProvider {
+                provider: provider.clone(),
+                configuration: Default::default(),
+            },
+        );
+        (provider, LvmProviders::Registered(name.to_string()))
+    }
+
+    fn request(prompt: &str, seed: Option<u32>) -> TextToImageRequest {
+        TextToImageRequest {
+            prompt: crate::ImagePrompt {
+                positive_prompt: Some(prompt.to_string()),
+                negative_prompt: None,
+            },
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache() -> Result<()> {
+        let (counting, provider) = counting("cache-test-memory");
+        let cached = CachedProvider::new(provider, CacheBackend::Memory { capacity: 1 });
+        let provider = LvmProviders::Cached(cached.clone());
+
+        let images = provider.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(images[0].data, b"cat");
+        let images = provider.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(images[0].data, b"cat");
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 1);
+
+        // Unseeded requests aren't cached.
+        provider.text_to_image(request("cat", None)).await?;
+        provider.text_to_image(request("cat", None)).await?;
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 3);
+
+        // The cache only holds one request, so the first one is evicted.
+        provider.text_to_image(request("dog", Some(1))).await?;
+        provider.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache() -> Result<()> {
+        let dir = tempdir()?;
+        let (counting, provider) = counting("cache-test-disk");
+        let backend = CacheBackend::Disk {
+            path: dir.path().to_path_buf(),
+        };
+        let cached = CachedProvider::new(provider.clone(), backend.clone());
+        cached.text_to_image(request("cat", Some(1))).await?;
+
+        // A new provider reads the images cached by the first one.
+        let cached = CachedProvider::new(provider.clone(), backend.clone());
+        let images = cached.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(images[0].data, b"cat");
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 1);
+
+        let bypassed = CachedProvider::new(provider.clone(), backend.clone()).with_bypass(true);
+        bypassed.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 2);
+
+        let expired = CachedProvider::new(provider, backend).with_ttl_secs(0);
+        expired.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key() {
+        let (_, provider) = counting("cache-test-key");
+        let cached = CachedProvider::new(provider, CacheBackend::Memory { capacity: 1 });
+        assert_eq!(
+            cached.key(&request("cat", Some(1))),
+            cached.key(&request("cat", Some(1)))
+        );
+        assert_ne!(
+            cached.key(&request("cat", Some(1))),
+            cached.key(&request("cat", Some(2)))
+        );
+        assert_eq!(cached.key(&request("cat", None)), None);
+
+        let (_, other) = counting("cache-test-key-other");
+        let other = CachedProvider::new(other, CacheBackend::Memory { capacity: 1 });
+        assert_ne!(
+            cached.key(&request("cat", Some(1))),
+            other.key(&request("cat", Some(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_providers_share_disk_cache() -> Result<()> {
+        let dir = tempdir()?;
+        let backend = CacheBackend::Disk {
+            path: dir.path().to_path_buf(),
+        };
+        let (first_counting, second_counting) = (
+            Arc::new(CountingProvider::default()),
+            Arc::new(CountingProvider::default()),
+        );
+        let first = CachedProvider::new(
+            LvmProviders::Custom(CustomProvider {
+                provider: first_counting.clone(),
+                configuration: Default::default(),
+            }),
+            backend.clone(),
+        );
+        let second = CachedProvider::new(
+            LvmProviders::Custom(CustomProvider {
+                provider: second_counting.clone(),
+                configuration: Default::default(),
+            }),
+            backend,
+        );
+        assert_eq!(first.key(&request("cat", Some(1))), None);
+
+        first.text_to_image(request("cat", Some(1))).await?;
+        second.text_to_image(request("cat", Some(1))).await?;
+        assert_eq!(first_counting.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(second_counting.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Endpoints for queueing workflows and fetching their outputs.
+
+use super::ComfyUiProvider;
+use crate::instrumentation::{self, SendTraced, event, in_span};
+use anyhow::{Result, anyhow};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::Message;
+
+pub(crate) type PromptId = String;
+
+#[derive(Debug, Deserialize)]
+struct QueuePromptResponse {
+    prompt_id: PromptId,
+}
+
+/// The history of a single prompt.
+#[derive(Debug, Deserialize)]
+pub(crate) struct PromptHistory {
+    /// The outputs of each node that produced any.
+    #[serde(default)]
+    pub outputs: HashMap<String, NodeOutput>,
+    pub status: Option<PromptStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PromptStatus {
+    pub status_str: String,
+    pub completed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct NodeOutput {
+    #[serde(default)]
+    pub images: Vec<OutputImage>,
+}
+
+/// A reference to an image stored by ComfyUI.
+#[derive(Debug, Deserialize)]
+pub(crate) struct OutputImage {
+    pub filename: String,
+    pub subfolder: String,
+    #[serde(rename = "type")]
+    pub folder_type: String,
+}
+
+/// A message from the websocket progress feed.
+#[derive(Debug, Deserialize)]
+struct FeedMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    #[serde(default)]
+    data: Value,
+}
+
+impl ComfyUiProvider {
+    /// Send a POST request to `/prompt` to queue a workflow.
+    /// The response contains the prompt_id used to track the workflow.
+    pub(crate) async fn queue_prompt(&self, workflow: &Value) -> Result<PromptId> {
+        let url = format!("{}/prompt", self.base_url);
+        let body = json!({
+            "prompt": workflow,
+            "client_id": self.client_id,
+        });
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&body)
+            .send_traced()
+            .await?;
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let response = response.text().await?;
+            return Err(anyhow!(
+                "Failed to queue prompt. Request URL: {:?}, Response: {:?}",
+                &url,
+                response
+            ));
+        }
+        let response: QueuePromptResponse = response.json().await?;
+        Ok(response.prompt_id)
+    }
+
+    /// Send a GET request to `/history/{prompt_id}`.
+    /// Returns `None` if the prompt has not finished yet.
+    pub(crate) async fn get_history(&self, prompt_id: &PromptId) -> Result<Option<PromptHistory>> {
+        let url = format!("{}/history/{}", self.base_url, prompt_id);
+        let mut response: HashMap<String, PromptHistory> = instrumentation::get(&url)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.remove(prompt_id))
+    }
+
+    /// Send a GET request to `/view` to download an output image.
+    pub(crate) async fn get_image(&self, image: &OutputImage) -> Result<Vec<u8>> {
+        let url = format!("{}/view", self.base_url);
+        let response = reqwest::Client::new()
+            .get(&url)
+            .query(&[
+                ("filename", image.filename.as_str()),
+                ("subfolder", image.subfolder.as_str()),
+                ("type", image.folder_type.as_str()),
+            ])
+            .send_traced()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Connect to the websocket progress feed for this provider's client ID.
+    pub(crate) async fn connect_feed(&self) -> Result<ProgressFeed> {
+        let url = format!(
+            "{}/ws?clientId={}",
+            self.base_url
+                .replacen("https://", "wss://", 1)
+                .replacen("http://", "ws://", 1),
+            self.client_id
+        );
+        let (stream, _) = tokio_tungstenite::connect_async(url).await?;
+        Ok(ProgressFeed { stream })
+    }
+
+    /// Poll `/history` until the prompt has finished.
+    pub(crate) async fn poll_history(&self, prompt_id: &PromptId) -> Result<PromptHistory> {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let history = in_span!(
+                async {
+                    let history = self.get_history(prompt_id).await?;
+                    event!(debug, finished = history.is_some(), "Polled prompt history");
+                    anyhow::Ok(history)
+                },
+                tracing::debug_span!("poll_history", prompt_id = %prompt_id)
+            )
+            .await?;
+            if let Some(history) = history {
+                return Ok(history);
+            }
+        }
+    }
+}
+
+/// The websocket progress feed of a ComfyUI instance.
+pub(crate) struct ProgressFeed {
+    stream: tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+}
+
+impl ProgressFeed {
+    /// Wait until ComfyUI reports that the prompt has finished executing.
+    /// Returns `Ok(false)` if the feed closed before the prompt finished.
+    pub(crate) async fn wait_for(&mut self, prompt_id: &PromptId) -> Result<bool> {
+        while let Some(message) = self.stream.next().await {
+            // Binary messages are previews of the image being generated.
+            let Message::Text(text) = message? else {
+                continue;
+            };
+            let Ok(message) = serde_json::from_str::<FeedMessage>(&text) else {
+                continue;
+            };
+            if message.data["prompt_id"].as_str() != Some(prompt_id) {
+                continue;
+            }
+            match message.message_type.as_str() {
+                // ComfyUI signals that a prompt has finished by reporting that no node is executing.
+                "executing" if message.data["node"].is_null() => return Ok(true),
+                "execution_success" => return Ok(true),
+                "execution_error" => {
+                    return Err(anyhow!(
+                        "Prompt failed: {}",
+                        message.data["exception_message"]
+                            .as_str()
+                            .unwrap_or("unknown error")
+                    ));
+                }
+                "execution_interrupted" => return Err(anyhow!("Prompt was interrupted.")),
+                _ => {}
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use tokio::net::TcpListener;
+
+    /// Start a websocket server that sends the given messages to the first client that connects.
+    async fn feed_server(messages: Vec<Value>) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut websocket = tokio_tungstenite::accept_async(stream).await?;
+            for message in messages {
+                websocket.send(Message::text(message.to_string())).await?;
+            }
+            anyhow::Ok(())
+        });
+        Ok(format!("http://{}", address))
+    }
+
+    #[tokio::test]
+    async fn test_wait_for() -> Result<()> {
+        let base_url = feed_server(vec![
+            json!({ "type": "status", "data": { "status": {} } }),
+            json!({ "type": "executing", "data": { "node": null, "prompt_id": "other" } }),
+            json!({ "type": "progress", "data": { "value": 1, "max": 20, "prompt_id": "1234" } }),
+            json!({ "type": "executing", "data": { "node": null, "prompt_id": "1234" } }),
+        ])
+        .await?;
+        let provider = ComfyUiProvider {
+            base_url,
+            client_id: "test".to_string(),
+        };
+        let mut feed = provider.connect_feed().await?;
+        assert!(feed.wait_for(&"1234".to_string()).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_error() -> Result<()> {
+        let base_url = feed_server(vec![json!({
+            "type": "execution_error",
+            "data": { "prompt_id": "1234", "exception_message": "Out of memory" }
+        })])
+        .await?;
+        let provider = ComfyUiProvider {
+            base_url,
+            client_id: "test".to_string(),
+        };
+        let mut feed = provider.connect_feed().await?;
+        let error = feed.wait_for(&"1234".to_string()).await.unwrap_err();
+        assert!(error.to_string().contains("Out of memory"));
+        Ok(())
+    }
+}
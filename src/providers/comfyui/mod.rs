@@ -0,0 +1,244 @@
+mod api;
+pub mod workflow;
+
+use crate::{
+    images::{LvmImage, LvmImageMetadata},
+    parameters::{
+        provider::ProviderConfiguration, size::ImageSize, text_to_image::TextToImageRequest,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use api::{PromptHistory, PromptId};
+use async_trait::async_trait;
+use base64::Engine;
+
+const DEFAULT_BASE_URL: &str = "http://127.0.0.1:8188";
+
+/// How long to wait for a workflow to finish.
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// How long to wait for the websocket progress feed to connect before polling instead.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A provider for generating images with a ComfyUI instance.
+/// Requests are injected into a workflow graph which is queued on the instance.
+#[derive(Debug, Clone)]
+pub struct ComfyUiProvider {
+    pub base_url: String,
+    /// Identifies this provider on the websocket progress feed.
+    pub client_id: String,
+}
+
+impl Default for ComfyUiProvider {
+    fn default() -> Self {
+        Self::from(&ProviderConfiguration::default())
+    }
+}
+
+impl From<&ProviderConfiguration> for ComfyUiProvider {
+    fn from(config: &ProviderConfiguration) -> Self {
+        ComfyUiProvider {
+            base_url: config
+                .base_url
+                .clone()
+                .unwrap_or(DEFAULT_BASE_URL.to_string()),
+            client_id: format!("lvm-multi-api-{:x}", super::random_seed()),
+        }
+    }
+}
+
+impl ComfyUiProvider {
+    /// Queue a workflow and wait for it to finish, returning the images it saved.
+    pub async fn run_workflow(&self, workflow: serde_json::Value) -> Result<Vec<LvmImage>> {
+        // Connect to the progress feed before queueing so no messages are missed.
+        // If the feed is not available, fall back to polling the history.
+        let feed = tokio::time::timeout(CONNECT_TIMEOUT, self.connect_feed())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        let prompt_id = self.queue_prompt(&workflow).await?;
+
+        let history = tokio::time::timeout(TIMEOUT, async {
+            let finished = match feed {
+                Some(mut feed) => feed.wait_for(&prompt_id).await?,
+                None => false,
+            };
+            if finished {
+                self.get_history(&prompt_id)
+                    .await?
+                    .ok_or_else(|| anyhow!("History for prompt {} is not available.", prompt_id))
+            } else {
+                self.poll_history(&prompt_id).await
+            }
+        })
+        .await
+        .map_err(|_| anyhow!("Prompt {} timed out.", prompt_id))??;
+
+        let generation_params = serde_json::to_string(&workflow).ok();
+        let images = self
+            .download_outputs(&prompt_id, history)
+            .await?
+            .into_iter()
+            .map(|image| LvmImage {
+                data: base64::prelude::BASE64_STANDARD
+                    .encode(image)
+                    .as_bytes()
+                    .to_vec(),
+                metadata: Some(LvmImageMetadata {
+                    generation_params: generation_params.clone(),
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        Ok(images)
+    }
+
+    /// Download the images saved by a finished prompt, ordered by node ID.
+    async fn download_outputs(
+        &self,
+        prompt_id: &PromptId,
+        history: PromptHistory,
+    ) -> Result<Vec<Vec<u8>>> {
+        if let Some(status) = &history.status
+            && (status.status_str == "error" || !status.completed)
+        {
+            return Err(anyhow!(
+                "Prompt {} did not complete. Status: {}",
+                prompt_id,
+                status.status_str
+            ));
+        }
+
+        let mut outputs: Vec<_> = history.outputs.into_iter().collect();
+        outputs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut images = Vec::new();
+        for (_, output) in outputs {
+            // Skip previews, which are stored in the temp folder.
+            for image in output
+                .images
+                .iter()
+                .filter(|image| image.folder_type == "output")
+            {
+                images.push(self.get_image(image).await?);
+            }
+        }
+        Ok(images)
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for ComfyUiProvider {
+    /// Generate images using the request's workflow template, or the default text-to-image workflow.
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        let workflow = workflow::request_workflow(&request)?;
+        self.run_workflow(workflow).await
+    }
+
+    // Each batch is a separate workflow, which can generate several images through `batch_size`.
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        Some(1)
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        crate::providers::automatic1111::resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::prompt::ImagePrompt;
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_partial_json, method, path, query_param},
+    };
+
+    /// Generate an image against a stand-in server without a websocket feed, so the history is polled.
+    #[tokio::test]
+    async fn test_text_to_image() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/prompt"))
+            .and(body_partial_json(json!({
+                "prompt": { "6": { "inputs": { "text": "A painting of a cat" } } }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "prompt_id": "1234",
+                "number": 0,
+                "node_errors": {},
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/history/1234"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "1234": {
+                    "outputs": {
+                        "9": {
+                            "images": [
+                                { "filename": "cat.png", "subfolder": "", "type": "output" },
+                                { "filename": "preview.png", "subfolder": "", "type": "temp" },
+                            ]
+                        }
+                    },
+                    "status": { "status_str": "success", "completed": true },
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/view"))
+            .and(query_param("filename", "cat.png"))
+            .and(query_param("type", "output"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"image".to_vec()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = ComfyUiProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        });
+        let request = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: None,
+            },
+            ..Default::default()
+        };
+        let images = provider.text_to_image(request).await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].decode()?, b"image");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_failed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/prompt"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "prompt_id": "1234" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/history/1234"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "1234": {
+                    "outputs": {},
+                    "status": { "status_str": "error", "completed": false },
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = ComfyUiProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        });
+        let result = provider.text_to_image(TextToImageRequest::default()).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! Workflow templates submitted to ComfyUI.
+//!
+//! A template is a workflow in ComfyUI's API format (as exported with "Save (API Format)")
+//! together with bindings that say which node inputs the fields of a request and named variables are written to.
+
+use crate::{
+    parameters::{
+        field::RequestField,
+        text_to_image::{TextToImageRequest, WorkflowVariable},
+    },
+    providers::random_seed,
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// The default text-to-image workflow in ComfyUI's API format.
+const DEFAULT_WORKFLOW: &str = include_str!("default_workflow.json");
+
+/// Where the value bound to a node input comes from.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum BindingSource {
+    /// A field of the request.
+    Field(RequestField),
+    /// A named variable from the request's ComfyUI parameters.
+    Variable(String),
+}
+
+/// Writes a value to the input of a node.
+/// Inputs whose value is not set in the request keep the value from the workflow.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Binding {
+    #[serde(flatten)]
+    pub source: BindingSource,
+    /// The ID of the node.
+    pub node: String,
+    /// The name of the input on the node.
+    pub input: String,
+}
+
+/// A workflow together with the bindings used to fill it in from a request.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct WorkflowTemplate {
+    /// The workflow in ComfyUI's API format.
+    pub workflow: Value,
+    #[serde(default)]
+    pub bindings: Vec<Binding>,
+}
+
+impl Default for WorkflowTemplate {
+    /// The built-in text-to-image workflow.
+    #[allow(clippy::expect_used)]
+    fn default() -> Self {
+        let workflow =
+            serde_json::from_str(DEFAULT_WORKFLOW).expect("The default workflow is valid JSON.");
+        WorkflowTemplate::new(workflow)
+            .bind(RequestField::Seed, "3", "seed")
+            .bind(RequestField::Steps, "3", "steps")
+            .bind(RequestField::CfgScale, "3", "cfg")
+            .bind(RequestField::SamplerName, "3", "sampler_name")
+            .bind(RequestField::Model, "4", "ckpt_name")
+            .bind(RequestField::Width, "5", "width")
+            .bind(RequestField::Height, "5", "height")
+            .bind(RequestField::BatchSize, "5", "batch_size")
+            .bind(RequestField::PositivePrompt, "6", "text")
+            .bind(RequestField::NegativePrompt, "7", "text")
+    }
+}
+
+impl WorkflowTemplate {
+    /// Create a template from a workflow in ComfyUI's API format, without any bindings.
+    pub fn new(workflow: Value) -> Self {
+        WorkflowTemplate {
+            workflow,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Load a template from a JSON file of the form `{"workflow": ..., "bindings": [...]}`.
+    /// `workflow` is either the workflow itself or the path to an exported workflow, relative to the template.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let mut template: WorkflowTemplate = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if let Value::String(workflow_path) = &template.workflow {
+            let workflow_path = path.parent().unwrap_or(Path::new("")).join(workflow_path);
+            template.workflow = serde_json::from_str(&std::fs::read_to_string(workflow_path)?)?;
+        }
+        Ok(template)
+    }
+
+    /// Bind a field of the request to a node input.
+    pub fn bind(mut self, field: RequestField, node: &str, input: &str) -> Self {
+        self.bindings.push(Binding {
+            source: BindingSource::Field(field),
+            node: node.to_string(),
+            input: input.to_string(),
+        });
+        self
+    }
+
+    /// Bind a named variable to a node input.
+    pub fn bind_variable(mut self, name: &str, node: &str, input: &str) -> Self {
+        self.bindings.push(Binding {
+            source: BindingSource::Variable(name.to_string()),
+            node: node.to_string(),
+            input: input.to_string(),
+        });
+        self
+    }
+
+    /// Fill in the workflow with the fields of the request and the given variables.
+    /// Seeds that are not set are randomised, since ComfyUI caches the outputs of identical workflows.
+    pub fn render(
+        &self,
+        request: &TextToImageRequest,
+        variables: &[WorkflowVariable],
+    ) -> Result<Value> {
+        let mut workflow = self.workflow.clone();
+        for binding in &self.bindings {
+            let value = match &binding.source {
+                BindingSource::Field(RequestField::Seed) => Some(
+                    RequestField::Seed
+                        .value(request)
+                        .unwrap_or_else(|| random_seed().into()),
+                ),
+                BindingSource::Field(field) => field.value(request),
+                BindingSource::Variable(name) => variables
+                    .iter()
+                    .find(|variable| &variable.name == name)
+                    .map(|variable| variable.value.clone()),
+            };
+            if let Some(value) = value {
+                set_input(&mut workflow, &binding.node, &binding.input, value)?;
+            }
+        }
+        Ok(workflow)
+    }
+}
+
+/// Set the input of a node in a workflow.
+fn set_input(workflow: &mut Value, node: &str, input: &str, value: Value) -> Result<()> {
+    let inputs = workflow
+        .get_mut(node)
+        .and_then(|node| node.get_mut("inputs"))
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| anyhow!("Workflow does not contain a node with ID {:?}.", node))?;
+    inputs.insert(input.to_string(), value);
+    Ok(())
+}
+
+/// Build the workflow for a request, using the template from its ComfyUI parameters or the default template.
+pub fn request_workflow(request: &TextToImageRequest) -> Result<Value> {
+    let parameters = request.comfyui.clone().unwrap_or_default();
+    let template = match &parameters.workflow {
+        Some(path) => WorkflowTemplate::from_file(path)?,
+        None => WorkflowTemplate::default(),
+    };
+    template.render(request, &parameters.variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        prompt::ImagePrompt,
+        text_to_image::{ComfyUiRequestParameters, TextToImageRequestExtendedParameters},
+    };
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_default_workflow() -> Result<()> {
+        let request = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: Some("dog".to_string()),
+            },
+            model: Some("sd_xl_base_1.0.safetensors".to_string()),
+            width: Some(1024),
+            height: Some(768),
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed: Some(1234),
+                steps: Some(10),
+                cfg_scale: Some(3.5),
+                sampler_name: Some("dpmpp_2m".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let workflow = request_workflow(&request)?;
+        assert_eq!(workflow["6"]["inputs"]["text"], "A painting of a cat");
+        assert_eq!(workflow["7"]["inputs"]["text"], "dog");
+        assert_eq!(
+            workflow["4"]["inputs"]["ckpt_name"],
+            "sd_xl_base_1.0.safetensors"
+        );
+        assert_eq!(workflow["5"]["inputs"]["width"], 1024);
+        assert_eq!(workflow["5"]["inputs"]["height"], 768);
+        assert_eq!(workflow["3"]["inputs"]["seed"], 1234);
+        assert_eq!(workflow["3"]["inputs"]["steps"], 10);
+        assert_eq!(workflow["3"]["inputs"]["cfg"], 3.5);
+        assert_eq!(workflow["3"]["inputs"]["sampler_name"], "dpmpp_2m");
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_workflow_random_seed() -> Result<()> {
+        let request = TextToImageRequest::default();
+        let first = request_workflow(&request)?;
+        let second = request_workflow(&request)?;
+        assert_ne!(first["3"]["inputs"]["seed"], second["3"]["inputs"]["seed"]);
+        // Fields that are not set keep the values from the template.
+        assert_eq!(first["3"]["inputs"]["steps"], 20);
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_template() -> Result<()> {
+        let dir = tempdir()?;
+        std::fs::write(
+            dir.path().join("upscale.json"),
+            json!({
+                "1": { "class_type": "PromptNode", "inputs": { "text": "" } },
+                "2": { "class_type": "LoraLoader", "inputs": { "strength_model": 1.0 } },
+            })
+            .to_string(),
+        )?;
+        let template_path = dir.path().join("template.json");
+        std::fs::write(
+            &template_path,
+            json!({
+                "workflow": "upscale.json",
+                "bindings": [
+                    { "field": "positive_prompt", "node": "1", "input": "text" },
+                    { "variable": "lora_strength", "node": "2", "input": "strength_model" },
+                ],
+            })
+            .to_string(),
+        )?;
+
+        let request = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: None,
+            },
+            comfyui: Some(ComfyUiRequestParameters {
+                workflow: Some(template_path),
+                variables: vec!["lora_strength=0.5".parse()?],
+            }),
+            ..Default::default()
+        };
+        let workflow = request_workflow(&request)?;
+        assert_eq!(workflow["1"]["inputs"]["text"], "A painting of a cat");
+        assert_eq!(workflow["2"]["inputs"]["strength_model"], 0.5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_missing_node() {
+        let template = WorkflowTemplate::new(json!({})).bind(RequestField::Width, "5", "width");
+        let request = TextToImageRequest {
+            width: Some(512),
+            ..Default::default()
+        };
+        assert!(template.render(&request, &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_variable() -> Result<()> {
+        let variable: WorkflowVariable = "name=value".parse()?;
+        assert_eq!(variable.value, json!("value"));
+        let variable: WorkflowVariable = "strength=0.5".parse()?;
+        assert_eq!(variable.value, json!(0.5));
+        assert!("strength".parse::<WorkflowVariable>().is_err());
+        Ok(())
+    }
+}
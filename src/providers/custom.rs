@@ -0,0 +1,219 @@
+//! Providers implemented outside of this crate.
+//!
+//! A downstream crate implements [`TextToImageProvider`] for its own type, then either wraps it in
+//! [`LvmProviders::Custom`](crate::LvmProviders::Custom) directly, or registers it under a name with
+//! [`register_provider`] so it can be selected with [`LvmProviders::Registered`](crate::LvmProviders::Registered),
+//! e.g. from a configuration file or the CLI.
+
+use crate::{parameters::provider::ProviderConfiguration, traits::TextToImageProvider};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+/// Providers registered by name.
+static REGISTRY: LazyLock<RwLock<HashMap<String, CustomProvider>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// A provider implemented outside of this crate.
+#[derive(Clone)]
+pub struct CustomProvider {
+    pub provider: Arc<dyn TextToImageProvider>,
+    /// Settings used by this crate when sending requests to the provider, e.g. `max_concurrent_requests`.
+    pub configuration: ProviderConfiguration,
+}
+
+impl CustomProvider {
+    pub fn new(provider: impl TextToImageProvider + 'static) -> Self {
+        CustomProvider {
+            provider: Arc::new(provider),
+            configuration: ProviderConfiguration::default(),
+        }
+    }
+
+    pub fn with_configuration(mut self, configuration: ProviderConfiguration) -> Self {
+        self.configuration = configuration;
+        self
+    }
+}
+
+impl std::fmt::Debug for CustomProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomProvider")
+            .field("configuration", &self.configuration)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for CustomProvider {
+    /// Custom providers are equal if they wrap the same provider instance.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.provider, &other.provider) && self.configuration == other.configuration
+    }
+}
+
+/// Register a provider under a name, replacing any provider already registered under it.
+pub fn register_provider(name: &str, provider: CustomProvider) {
+    REGISTRY
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .insert(name.to_string(), provider);
+}
+
+/// Get the provider registered under a name.
+pub fn registered_provider(name: &str) -> Option<CustomProvider> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get(name)
+        .cloned()
+}
+
+/// The names of all registered providers, in alphabetical order.
+pub fn registered_provider_names() -> Vec<String> {
+    let mut names: Vec<String> = REGISTRY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        LvmProviders,
+        images::LvmImage,
+        parameters::{size::ImageSize, text_to_image::TextToImageRequest},
+    };
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Generates blank images and counts how many requests it was sent.
+    #[derive(Default)]
+    struct CountingProvider {
+        requests: AtomicU32,
+    }
+
+    #[async_trait]
+    impl TextToImageProvider for CountingProvider {
+        async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Ok((0..request.num_batches.unwrap_or(1))
+                .map(|_| LvmImage {
+                    data: b"aW1hZ2U=".to_vec(),
+                    metadata: None,
+                })
+                .collect())
+        }
+
+        fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+            Some(2)
+        }
+
+        fn resolve_size(
+            &self,
+            _request: &TextToImageRequest,
+            size: &ImageSize,
+        ) -> Result<(u32, u32)> {
+            size.resolve_to_multiple(64, 512 * 512)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registered_provider() -> Result<()> {
+        let counting = Arc::new(CountingProvider::default());
+        register_provider(
+            "counting",
+            CustomProvider {
+                provider: counting.clone(),
+                configuration: ProviderConfiguration::default(),
+            },
+        );
+        assert!(registered_provider_names().contains(&"counting".to_string()));
+
+        let provider = LvmProviders::Registered("counting".to_string());
+        let request = TextToImageRequest {
+            width: Some(500),
+            height: Some(500),
+            num_batches: Some(3),
+            ..Default::default()
+        };
+        let images = provider.text_to_image(request).await?;
+        assert_eq!(images.len(), 3);
+        assert_eq!(counting.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(images[0].metadata.as_ref().and_then(|m| m.width), Some(512));
+        Ok(())
+    }
+
+    /// Generates one image per request after a short delay, recording the most requests it was sent at once.
+    #[derive(Default)]
+    struct ConcurrencyProvider {
+        running: AtomicU32,
+        max_running: AtomicU32,
+    }
+
+    #[async_trait]
+    impl TextToImageProvider for ConcurrencyProvider {
+        async fn text_to_image(&self, _request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(vec![LvmImage {
+                data: b"aW1hZ2U=".to_vec(),
+                metadata: None,
+            }])
+        }
+
+        fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+            Some(1)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registered_max_concurrent_requests() -> Result<()> {
+        let concurrency = Arc::new(ConcurrencyProvider::default());
+        register_provider(
+            "one-at-a-time",
+            CustomProvider {
+                provider: concurrency.clone(),
+                configuration: ProviderConfiguration {
+                    max_concurrent_requests: Some(1),
+                    ..Default::default()
+                },
+            },
+        );
+
+        let provider = LvmProviders::Registered("one-at-a-time".to_string());
+        let request = TextToImageRequest {
+            num_batches: Some(4),
+            ..Default::default()
+        };
+        let images = provider.text_to_image(request).await?;
+        assert_eq!(images.len(), 4);
+        assert_eq!(concurrency.max_running.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unregistered_provider() {
+        let provider = LvmProviders::Registered("missing".to_string());
+        let error = provider
+            .text_to_image(TextToImageRequest::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("missing"));
+    }
+
+    #[test]
+    fn test_custom_provider_eq() {
+        let provider = CustomProvider::new(CountingProvider::default());
+        assert_eq!(provider, provider.clone());
+        assert_ne!(provider, CustomProvider::new(CountingProvider::default()));
+    }
+}
@@ -0,0 +1,253 @@
+//! Try several providers in order until one of them generates the images.
+
+use crate::{
+    errors::ErrorKind,
+    images::LvmImage,
+    instrumentation::metrics,
+    parameters::{size::ImageSize, text_to_image::TextToImageRequest},
+    providers::{batch::BatchResults, index::LvmProviders},
+    traits::TextToImageProvider,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{future::Future, pin::Pin};
+
+/// A provider in a fallback chain, with how to adapt requests for it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FallbackStep {
+    pub provider: LvmProviders,
+    /// The model to request from this provider.
+    /// If not set, the request's model is only sent to the first provider, since model names differ between providers.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// The size to request from this provider instead of the request's size.
+    #[serde(default)]
+    pub size: Option<ImageSize>,
+}
+
+impl From<LvmProviders> for FallbackStep {
+    fn from(provider: LvmProviders) -> Self {
+        FallbackStep {
+            provider,
+            model: None,
+            size: None,
+        }
+    }
+}
+
+/// Sends a request to each provider in turn until one of them generates images.
+/// The next provider is only tried if the previous one failed with an error of a kind listed in `fallback_on`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FallbackProvider {
+    pub steps: Vec<FallbackStep>,
+    /// The kinds of errors that cause the next provider to be tried.
+    #[serde(default = "default_fallback_on")]
+    pub fallback_on: Vec<ErrorKind>,
+}
+
+/// By default, fall back on any error except requests that no provider would accept.
+fn default_fallback_on() -> Vec<ErrorKind> {
+    vec![
+        ErrorKind::Configuration,
+        ErrorKind::Connection,
+        ErrorKind::Timeout,
+        ErrorKind::RateLimited,
+        ErrorKind::ServerError,
+        ErrorKind::ContentRejected,
+    ]
+}
+
+impl FallbackProvider {
+    pub fn new(providers: impl IntoIterator<Item = impl Into<FallbackStep>>) -> Self {
+        FallbackProvider {
+            steps: providers.into_iter().map(Into::into).collect(),
+            fallback_on: default_fallback_on(),
+        }
+    }
+
+    pub fn with_fallback_on(mut self, fallback_on: Vec<ErrorKind>) -> Self {
+        self.fallback_on = fallback_on;
+        self
+    }
+
+    /// Adapt a request for the step at `index` in the chain.
+    pub(crate) fn step_request(
+        &self,
+        index: usize,
+        request: &TextToImageRequest,
+    ) -> TextToImageRequest {
+        let step = &self.steps[index];
+        let mut request = request.clone();
+        match (&step.model, index) {
+            (Some(model), _) => request.model = Some(model.clone()),
+            (None, 0) => {}
+            (None, _) => request.model = None,
+        }
+        if let Some(size) = step.size {
+            request.size = Some(size);
+        }
+        request
+    }
+
+    /// Send the request to each provider in turn, returning the results of the first one that generated any images.
+    /// The errors of the providers that were tried before it are returned alongside them.
+    pub fn text_to_image_batched(
+        &self,
+        request: TextToImageRequest,
+    ) -> Pin<Box<dyn Future<Output = BatchResults> + Send + '_>> {
+        // Boxed, since a step can itself be a fallback chain.
+        Box::pin(async move {
+            let mut errors = Vec::new();
+            for (index, step) in self.steps.iter().enumerate() {
+                if index > 0 {
+                    metrics::record_retry("fallback");
+                }
+                let mut results = step
+                    .provider
+                    .text_to_image_batched(self.step_request(index, &request))
+                    .await;
+                let should_fall_back = results.images.is_empty()
+                    && results
+                        .errors
+                        .first()
+                        .is_some_and(|error| self.fallback_on.contains(&ErrorKind::of(error)));
+                errors.append(&mut results.errors);
+                if !should_fall_back {
+                    return BatchResults {
+                        images: results.images,
+                        errors,
+                    };
+                }
+            }
+            if errors.is_empty() {
+                errors.push(anyhow::anyhow!("The fallback chain has no providers."));
+            }
+            BatchResults {
+                images: Vec::new(),
+                errors,
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for FallbackProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::custom::CustomProvider;
+    use anyhow::anyhow;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    };
+
+    /// Fails every request with the same message, counting how many requests it was sent.
+    struct FailingProvider {
+        message: &'static str,
+        requests: AtomicU32,
+    }
+
+    #[async_trait]
+    impl TextToImageProvider for FailingProvider {
+        async fn text_to_image(&self, _request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!(self.message))
+        }
+    }
+
+    /// Generates one image, recording the model it was asked for.
+    struct ModelProvider;
+
+    #[async_trait]
+    impl TextToImageProvider for ModelProvider {
+        async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            Ok(vec![LvmImage {
+                data: b"aW1hZ2U=".to_vec(),
+                metadata: Some(crate::LvmImageMetadata {
+                    generation_params: request.model,
+                    ..Default::default()
+                }),
+            }])
+        }
+    }
+
+    fn failing(message: &'static str) -> (Arc<FailingProvider>, LvmProviders) {
+        let provider = Arc::new(FailingProvider {
+            message,
+            requests: AtomicU32::new(0),
+        });
+        let custom = LvmProviders::Custom(CustomProvider {
+            provider: provider.clone(),
+            configuration: Default::default(),
+        });
+        (provider, custom)
+    }
+
+    fn request() -> TextToImageRequest {
+        TextToImageRequest {
+            model: Some("dall-e-3".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback() -> Result<()> {
+        let (_, unavailable) = failing("Failed. Status: 503 Service Unavailable");
+        let provider = LvmProviders::Fallback(FallbackProvider::new([
+            FallbackStep::from(unavailable),
+            FallbackStep {
+                provider: LvmProviders::Custom(CustomProvider::new(ModelProvider)),
+                model: Some("sdxl".to_string()),
+                size: None,
+            },
+        ]));
+        let results = provider.text_to_image_batched(request()).await;
+        assert_eq!(results.errors.len(), 1);
+        assert_eq!(results.images.len(), 1);
+        let metadata = results.images[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata.generation_params.as_deref(), Some("sdxl"));
+        assert_eq!(metadata.provider.as_deref(), Some("custom"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fallback_clears_model() -> Result<()> {
+        let (_, unavailable) = failing("Failed. Status: 429 Too Many Requests");
+        let provider = LvmProviders::Fallback(FallbackProvider::new([
+            unavailable,
+            LvmProviders::Custom(CustomProvider::new(ModelProvider)),
+        ]));
+        let images = provider.text_to_image(request()).await?;
+        assert_eq!(images[0].metadata.as_ref().unwrap().generation_params, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_on_invalid_request() {
+        let (_, invalid) = failing("Failed. Status: 400 Bad Request");
+        let (next, next_custom) = failing("Failed. Status: 503 Service Unavailable");
+        let provider = LvmProviders::Fallback(FallbackProvider::new([invalid, next_custom]));
+        let error = provider.text_to_image(request()).await.unwrap_err();
+        assert!(error.to_string().contains("400"));
+        assert_eq!(next.requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_all_failed() {
+        let (first, first_custom) = failing("Failed. Status: 503 Service Unavailable");
+        let (second, second_custom) = failing("Failed. Status: 502 Bad Gateway");
+        let provider = FallbackProvider::new([first_custom, second_custom]);
+        let results = provider.text_to_image_batched(request()).await;
+        assert!(results.images.is_empty());
+        assert_eq!(results.errors.len(), 2);
+        assert_eq!(first.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(second.requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,293 @@
+//! Send a request to two providers and use whichever answers first.
+
+use crate::{
+    images::LvmImage,
+    parameters::text_to_image::TextToImageRequest,
+    providers::{batch::BatchResults, index::LvmProviders, middleware::inherit_headers},
+    traits::TextToImageProvider,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{sync::Notify, task::JoinSet};
+
+/// Which of the two providers of a hedged request generated the images.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Hedge {
+    Primary,
+    Secondary,
+}
+
+/// How many hedged requests each provider won.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct HedgeStats {
+    pub primary_wins: u64,
+    pub secondary_wins: u64,
+    /// Requests that neither provider generated any images for.
+    pub failures: u64,
+}
+
+#[derive(Debug, Default)]
+struct HedgeCounters {
+    primary_wins: AtomicU64,
+    secondary_wins: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// Sends each request to a primary and a secondary provider, returning the results of the first one that generates any images.
+/// The other request is cancelled once one of them succeeds, and both are cancelled if the hedged request is.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HedgedProvider {
+    pub primary: Box<LvmProviders>,
+    pub secondary: Box<LvmProviders>,
+    /// How long to wait for the primary provider before also sending the request to the secondary provider.
+    /// The secondary provider is sent the request straight away if this is `None`, or as soon as the primary provider fails.
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    /// Shared by clones, so requests sent through a shared provider are all counted.
+    #[serde(skip)]
+    counters: Arc<HedgeCounters>,
+}
+
+impl PartialEq for HedgedProvider {
+    /// Hedged providers are equal if they are configured the same, whatever their stats.
+    fn eq(&self, other: &Self) -> bool {
+        self.primary == other.primary
+            && self.secondary == other.secondary
+            && self.delay_ms == other.delay_ms
+    }
+}
+
+impl HedgedProvider {
+    pub fn new(primary: LvmProviders, secondary: LvmProviders) -> Self {
+        HedgedProvider {
+            primary: Box::new(primary),
+            secondary: Box::new(secondary),
+            delay_ms: None,
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay_ms = Some(delay.as_millis() as u64);
+        self
+    }
+
+    /// How many requests each provider has won so far.
+    pub fn stats(&self) -> HedgeStats {
+        HedgeStats {
+            primary_wins: self.counters.primary_wins.load(Ordering::Relaxed),
+            secondary_wins: self.counters.secondary_wins.load(Ordering::Relaxed),
+            failures: self.counters.failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Send the request to both providers and return the results of the first one to generate any images.
+    /// If neither does, the errors of both are returned.
+    pub fn text_to_image_batched(
+        &self,
+        request: TextToImageRequest,
+    ) -> Pin<Box<dyn Future<Output = BatchResults> + Send + '_>> {
+        // Boxed, since either provider can itself be hedged.
+        Box::pin(async move {
+            let primary_failed = Arc::new(Notify::new());
+            // Dropping the set aborts both tasks, so neither outlives the hedged request.
+            let mut tasks = JoinSet::new();
+            {
+                let provider = self.primary.as_ref().clone();
+                let request = request.clone();
+                let primary_failed = Arc::clone(&primary_failed);
+                tasks.spawn(inherit_headers(async move {
+                    let results = provider.text_to_image_batched(request).await;
+                    if results.images.is_empty() {
+                        primary_failed.notify_one();
+                    }
+                    (Hedge::Primary, results)
+                }));
+            }
+            {
+                let provider = self.secondary.as_ref().clone();
+                let delay = self.delay_ms.map(Duration::from_millis);
+                tasks.spawn(inherit_headers(async move {
+                    if let Some(delay) = delay {
+                        let _ = tokio::time::timeout(delay, primary_failed.notified()).await;
+                    }
+                    let results = provider.text_to_image_batched(request).await;
+                    (Hedge::Secondary, results)
+                }));
+            }
+
+            let mut errors = Vec::new();
+            while let Some(joined) = tasks.join_next().await {
+                let (hedge, mut results) = match joined {
+                    Ok(joined) => joined,
+                    Err(error) => {
+                        errors.push(error.into());
+                        continue;
+                    }
+                };
+                if !results.images.is_empty() {
+                    tasks.abort_all();
+                    let counter = match hedge {
+                        Hedge::Primary => &self.counters.primary_wins,
+                        Hedge::Secondary => &self.counters.secondary_wins,
+                    };
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    errors.append(&mut results.errors);
+                    return BatchResults {
+                        images: results.images,
+                        errors,
+                    };
+                }
+                errors.append(&mut results.errors);
+            }
+            self.counters.failures.fetch_add(1, Ordering::Relaxed);
+            BatchResults {
+                images: Vec::new(),
+                errors,
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for HedgedProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::custom::CustomProvider;
+    use anyhow::anyhow;
+    use std::sync::atomic::AtomicU32;
+
+    /// Generates an image named `name` after `delay`, or fails if `fail` is set.
+    struct SlowProvider {
+        name: &'static str,
+        delay: Duration,
+        fail: bool,
+        requests: AtomicU32,
+    }
+
+    #[async_trait]
+    impl TextToImageProvider for SlowProvider {
+        async fn text_to_image(&self, _request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(anyhow!("{} failed", self.name));
+            }
+            Ok(vec![LvmImage {
+                data: self.name.as_bytes().to_vec(),
+                metadata: None,
+            }])
+        }
+    }
+
+    fn slow(name: &'static str, delay_ms: u64, fail: bool) -> (Arc<SlowProvider>, LvmProviders) {
+        let provider = Arc::new(SlowProvider {
+            name,
+            delay: Duration::from_millis(delay_ms),
+            fail,
+            requests: AtomicU32::new(0),
+        });
+        let custom = LvmProviders::Custom(CustomProvider {
+            provider: provider.clone(),
+            configuration: Default::default(),
+        });
+        (provider, custom)
+    }
+
+    #[tokio::test]
+    async fn test_fastest_wins() -> Result<()> {
+        let (_, primary) = slow("primary", 500, false);
+        let (_, secondary) = slow("secondary", 10, false);
+        let hedged = HedgedProvider::new(primary, secondary);
+        let provider = LvmProviders::Hedged(hedged.clone());
+        let images = provider
+            .text_to_image(TextToImageRequest::default())
+            .await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].data, b"secondary");
+        assert_eq!(
+            hedged.stats(),
+            HedgeStats {
+                secondary_wins: 1,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delay() -> Result<()> {
+        let (_, primary) = slow("primary", 10, false);
+        let (secondary, secondary_custom) = slow("secondary", 10, false);
+        let hedged =
+            HedgedProvider::new(primary, secondary_custom).with_delay(Duration::from_secs(5));
+        let images = hedged.text_to_image(TextToImageRequest::default()).await?;
+        assert_eq!(images[0].data, b"primary");
+        assert_eq!(secondary.requests.load(Ordering::SeqCst), 0);
+        assert_eq!(hedged.stats().primary_wins, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_primary_failure_skips_delay() -> Result<()> {
+        let (_, primary) = slow("primary", 0, true);
+        let (_, secondary) = slow("secondary", 0, false);
+        let hedged = HedgedProvider::new(primary, secondary).with_delay(Duration::from_secs(60));
+        let results = tokio::time::timeout(
+            Duration::from_secs(5),
+            hedged.text_to_image_batched(TextToImageRequest::default()),
+        )
+        .await?;
+        assert_eq!(results.images[0].data, b"secondary");
+        assert_eq!(results.errors.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let (primary, primary_custom) = slow("primary", 200, false);
+        let (secondary, secondary_custom) = slow("secondary", 0, false);
+        let hedged = HedgedProvider::new(primary_custom, secondary_custom)
+            .with_delay(Duration::from_millis(100));
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(20),
+            hedged.text_to_image_batched(TextToImageRequest::default()),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        // Long enough for the delay to pass and the primary to finish, had they not been aborted.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(primary.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary.requests.load(Ordering::SeqCst), 0);
+        assert_eq!(hedged.stats(), HedgeStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_both_fail() {
+        let (_, primary) = slow("primary", 0, true);
+        let (_, secondary) = slow("secondary", 0, true);
+        let hedged = HedgedProvider::new(primary, secondary);
+        let results = hedged
+            .text_to_image_batched(TextToImageRequest::default())
+            .await;
+        assert!(results.images.is_empty());
+        assert_eq!(results.errors.len(), 2);
+        assert_eq!(hedged.stats().failures, 1);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    images::{LvmImage, LvmImageMetadata},
+    instrumentation::SendTraced,
+    parameters::{
+        provider::ProviderConfiguration, size::ImageSize, text_to_image::TextToImageRequest,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use base64::Engine;
+use dotenvy::dotenv;
+use serde_json::{Value, json};
+
+const DEFAULT_BASE_URL: &str = "https://api-inference.huggingface.co/models";
+const DEFAULT_API_KEY_ENV_VAR: &str = "HF_TOKEN";
+const DEFAULT_MODEL: &str = "stabilityai/stable-diffusion-xl-base-1.0";
+
+/// A provider for generating images with a Hugging Face Inference endpoint,
+/// or a self-hosted server exposing the same text-to-image API.
+/// Each request generates a single image.
+#[derive(Debug, Clone)]
+pub struct HuggingFaceProvider {
+    /// The URL requests are sent to. The request's model is appended to it, if it is set.
+    /// Defaults to the serverless Inference API, which requires a model.
+    pub base_url: Option<String>,
+    /// The name of the environment variable containing the access token.
+    /// No token is sent if the variable is not set, which self-hosted servers may not need.
+    pub api_key_env_var: String,
+}
+
+impl From<&ProviderConfiguration> for HuggingFaceProvider {
+    fn from(config: &ProviderConfiguration) -> Self {
+        HuggingFaceProvider {
+            base_url: config.base_url.clone(),
+            api_key_env_var: config
+                .api_key_env_var
+                .clone()
+                .unwrap_or(DEFAULT_API_KEY_ENV_VAR.to_string()),
+        }
+    }
+}
+
+impl HuggingFaceProvider {
+    /// The URL to send a request to.
+    /// The serverless Inference API uses the default model if the request doesn't set one.
+    fn url(&self, model: Option<&str>) -> String {
+        let (base_url, model) = match &self.base_url {
+            Some(base_url) => (base_url.as_str(), model),
+            None => (DEFAULT_BASE_URL, Some(model.unwrap_or(DEFAULT_MODEL))),
+        };
+        let base_url = base_url.trim_end_matches('/');
+        match model {
+            Some(model) => format!("{}/{}", base_url, model),
+            None => base_url.to_string(),
+        }
+    }
+}
+
+/// Convert a request into the body of a text-to-image request.
+fn to_huggingface_body(request: &TextToImageRequest) -> Value {
+    let extended = request.extended.clone().unwrap_or_default();
+    let mut parameters = json!({});
+    if let Some(negative_prompt) = &request.prompt.negative_prompt {
+        parameters["negative_prompt"] = negative_prompt.clone().into();
+    }
+    if let Some(guidance_scale) = extended.cfg_scale {
+        parameters["guidance_scale"] = guidance_scale.into();
+    }
+    if let Some(steps) = extended.steps {
+        parameters["num_inference_steps"] = steps.into();
+    }
+    if let Some(width) = request.width {
+        parameters["width"] = width.into();
+    }
+    if let Some(height) = request.height {
+        parameters["height"] = height.into();
+    }
+    if let Some(seed) = extended.seed {
+        parameters["seed"] = seed.into();
+    }
+    json!({
+        "inputs": request.prompt.positive_prompt.clone().unwrap_or_default(),
+        "parameters": parameters,
+    })
+}
+
+#[async_trait]
+impl TextToImageProvider for HuggingFaceProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        let url = self.url(request.model.as_deref());
+        let seed = request.extended.as_ref().and_then(|extended| extended.seed);
+        let mut http_request = reqwest::Client::new()
+            .post(&url)
+            .json(&to_huggingface_body(&request))
+            // Wait for the model to load instead of failing with a 503.
+            .header("x-wait-for-model", "true")
+            // The Inference API caches responses, so identical requests without a seed would return the same image.
+            .header("x-use-cache", seed.is_some().to_string());
+
+        // Load environment variables from a .env file, if there is one.
+        dotenv().ok();
+        if let Ok(token) = std::env::var(&self.api_key_env_var) {
+            http_request = http_request.bearer_auth(token);
+        }
+
+        let response = http_request.send_traced().await?;
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let status = response.status();
+            let response = response.text().await?;
+            return Err(anyhow!(
+                "Failed to generate image. Request URL: {:?}, Status: {}, Response: {:?}",
+                &url,
+                status,
+                response
+            ));
+        }
+        let is_image = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("image/"));
+        if !is_image {
+            let response = response.text().await?;
+            return Err(anyhow!(
+                "Expected an image in the response. Request URL: {:?}, Response: {:?}",
+                &url,
+                response
+            ));
+        }
+
+        let image = response.bytes().await?;
+        Ok(vec![LvmImage {
+            data: base64::prelude::BASE64_STANDARD
+                .encode(image)
+                .as_bytes()
+                .to_vec(),
+            metadata: Some(LvmImageMetadata {
+                generation_params: seed.map(|seed| format!("Seed: {}", seed)),
+                ..Default::default()
+            }),
+        }])
+    }
+
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        Some(1)
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        crate::providers::automatic1111::resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        prompt::ImagePrompt, text_to_image::TextToImageRequestExtendedParameters,
+    };
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_json, header, method, path},
+    };
+
+    fn request() -> TextToImageRequest {
+        TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: Some("dog".to_string()),
+            },
+            width: Some(768),
+            height: Some(512),
+            extended: Some(TextToImageRequestExtendedParameters {
+                steps: Some(25),
+                cfg_scale: Some(7.0),
+                seed: Some(42),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        let provider = HuggingFaceProvider::from(&ProviderConfiguration::default());
+        assert_eq!(
+            provider.url(None),
+            "https://api-inference.huggingface.co/models/stabilityai/stable-diffusion-xl-base-1.0"
+        );
+        let provider = HuggingFaceProvider::from(&ProviderConfiguration {
+            base_url: Some("http://localhost:8080/".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(provider.url(None), "http://localhost:8080");
+        assert_eq!(
+            provider.url(Some("my/model")),
+            "http://localhost:8080/my/model"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/"))
+            .and(header("x-use-cache", "true"))
+            .and(body_json(json!({
+                "inputs": "A painting of a cat",
+                "parameters": {
+                    "negative_prompt": "dog",
+                    "guidance_scale": 7.0,
+                    "num_inference_steps": 25,
+                    "width": 768,
+                    "height": 512,
+                    "seed": 42,
+                },
+            })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "image/png")
+                    .set_body_bytes(b"image".to_vec()),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = HuggingFaceProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            api_key_env_var: Some("LVM_MULTI_API_TEST_UNSET_HF_TOKEN".to_string()),
+            ..Default::default()
+        });
+        let images = provider.text_to_image(request()).await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].decode()?, b"image");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_not_an_image() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "error": "oops" })))
+            .mount(&server)
+            .await;
+
+        let provider = HuggingFaceProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        });
+        let error = provider.text_to_image(request()).await.unwrap_err();
+        assert!(error.to_string().contains("oops"));
+    }
+}
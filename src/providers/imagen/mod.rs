@@ -0,0 +1,319 @@
+use crate::{
+    errors::{ContentRejectedError, ProviderConfigurationError},
+    images::{LvmImage, LvmImageMetadata},
+    instrumentation::SendTraced,
+    parameters::{
+        provider::ProviderConfiguration, size::ImageSize, text_to_image::TextToImageRequest,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+const DEFAULT_API_KEY_ENV_VAR: &str = "GOOGLE_ACCESS_TOKEN";
+const DEFAULT_MODEL: &str = "imagen-3.0-generate-002";
+
+/// Imagen can generate up to this many images in a single request.
+pub const MAX_IMAGES_PER_REQUEST: u32 = 4;
+
+/// Imagen only accepts these aspect ratios, listed with the size it generates for each of them.
+const ASPECT_RATIOS: [(&str, (u32, u32)); 5] = [
+    ("1:1", (1024, 1024)),
+    ("3:4", (896, 1280)),
+    ("4:3", (1280, 896)),
+    ("9:16", (768, 1408)),
+    ("16:9", (1408, 768)),
+];
+
+/// A provider for generating images with Imagen through a Vertex AI-style `:predict` endpoint.
+#[derive(Debug, Clone)]
+pub struct ImagenProvider {
+    /// The URL of the publisher's models, e.g.
+    /// `https://us-central1-aiplatform.googleapis.com/v1/projects/{project}/locations/us-central1/publishers/google/models`.
+    pub base_url: Option<String>,
+    /// The name of the environment variable containing the bearer token, e.g. from `gcloud auth print-access-token`.
+    pub api_key_env_var: String,
+}
+
+impl From<&ProviderConfiguration> for ImagenProvider {
+    fn from(config: &ProviderConfiguration) -> Self {
+        ImagenProvider {
+            base_url: config.base_url.clone(),
+            api_key_env_var: config
+                .api_key_env_var
+                .clone()
+                .unwrap_or(DEFAULT_API_KEY_ENV_VAR.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PredictResponse {
+    /// Images removed by the safety filters are left out of the predictions.
+    #[serde(default)]
+    predictions: Vec<Prediction>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Prediction {
+    /// The image in base64 encoding.
+    bytes_base64_encoded: Option<String>,
+    /// Why the image was removed by the safety filters, if it was.
+    rai_filtered_reason: Option<String>,
+}
+
+/// Pick the supported size closest to the requested size.
+pub fn resolve_size(size: &ImageSize) -> Result<(u32, u32)> {
+    let sizes: Vec<(u32, u32)> = ASPECT_RATIOS.iter().map(|(_, size)| *size).collect();
+    size.resolve_from(&sizes)
+}
+
+/// Find the aspect ratio parameter for an image size.
+fn to_imagen_aspect_ratio(width: Option<u32>, height: Option<u32>) -> Option<&'static str> {
+    let (width, height) = (width?, height?);
+    let resolved = resolve_size(&ImageSize::AspectRatio { width, height }).ok()?;
+    ASPECT_RATIOS
+        .iter()
+        .find(|(_, size)| *size == resolved)
+        .map(|(aspect_ratio, _)| *aspect_ratio)
+}
+
+/// Convert a request into the body of a `:predict` request.
+fn to_imagen_body(request: &TextToImageRequest) -> Value {
+    let mut parameters = json!({ "sampleCount": request.num_batches.unwrap_or(1) });
+    if let Some(aspect_ratio) = to_imagen_aspect_ratio(request.width, request.height) {
+        parameters["aspectRatio"] = aspect_ratio.into();
+    }
+    if let Some(negative_prompt) = &request.prompt.negative_prompt {
+        parameters["negativePrompt"] = negative_prompt.clone().into();
+    }
+    let seed = request.extended.as_ref().and_then(|extended| extended.seed);
+    if let Some(seed) = seed {
+        parameters["seed"] = seed.into();
+    }
+    let imagen = request.imagen.clone().unwrap_or_default();
+    if let Some(person_generation) = imagen.person_generation {
+        parameters["personGeneration"] = json!(person_generation);
+    }
+    if let Some(safety_setting) = imagen.safety_setting {
+        parameters["safetySetting"] = json!(safety_setting);
+    }
+    // Seeds are only used when the watermark is disabled.
+    match (imagen.add_watermark, seed) {
+        (Some(add_watermark), _) => parameters["addWatermark"] = add_watermark.into(),
+        (None, Some(_)) => parameters["addWatermark"] = false.into(),
+        (None, None) => {}
+    }
+    json!({
+        "instances": [{ "prompt": request.prompt.positive_prompt.clone().unwrap_or_default() }],
+        "parameters": parameters,
+    })
+}
+
+#[async_trait]
+impl TextToImageProvider for ImagenProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        let configuration = || ProviderConfiguration {
+            base_url: self.base_url.clone(),
+            api_key_env_var: Some(self.api_key_env_var.clone()),
+            ..Default::default()
+        };
+        let base_url = self.base_url.as_ref().ok_or_else(|| {
+            anyhow!(ProviderConfigurationError {
+                message: "base_url is required".to_string(),
+                configuration: configuration(),
+            })
+        })?;
+        // Load environment variables from a .env file, if there is one.
+        dotenv().ok();
+        let token = std::env::var(&self.api_key_env_var).map_err(|_| {
+            anyhow!(ProviderConfigurationError {
+                message: format!("{} environment variable not set", self.api_key_env_var),
+                configuration: configuration(),
+            })
+        })?;
+
+        let url = format!(
+            "{}/{}:predict",
+            base_url.trim_end_matches('/'),
+            request.model.as_deref().unwrap_or(DEFAULT_MODEL)
+        );
+        let response = reqwest::Client::new()
+            .post(&url)
+            .bearer_auth(token)
+            .json(&to_imagen_body(&request))
+            .send_traced()
+            .await?;
+
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let status = response.status();
+            let response = response.text().await?;
+            return Err(anyhow!(
+                "Failed to generate image. Request URL: {:?}, Status: {}, Response: {:?}",
+                &url,
+                status,
+                response
+            ));
+        }
+
+        let response: PredictResponse = response.json().await?;
+        let mut filtered_reasons = Vec::new();
+        let mut images = Vec::new();
+        for prediction in response.predictions {
+            match prediction.bytes_base64_encoded {
+                Some(image) => images.push(LvmImage {
+                    data: image.into_bytes(),
+                    metadata: Some(LvmImageMetadata::default()),
+                }),
+                None => filtered_reasons.extend(prediction.rai_filtered_reason),
+            }
+        }
+        if images.is_empty() {
+            let message = format!(
+                "No images were generated. Filtered reasons: {:?}",
+                filtered_reasons
+            );
+            // Images are only left out of the response when they are filtered by Imagen's safety filters.
+            if filtered_reasons.is_empty() {
+                return Err(anyhow!(message));
+            }
+            return Err(anyhow!(ContentRejectedError { message }));
+        }
+        Ok(images)
+    }
+
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        Some(MAX_IMAGES_PER_REQUEST)
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use crate::parameters::{
+        prompt::ImagePrompt,
+        text_to_image::{
+            ImagenPersonGeneration, ImagenRequestParameters, TextToImageRequestExtendedParameters,
+        },
+    };
+    use serial_test::serial;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_json, header, method, path},
+    };
+
+    const TEST_API_KEY_ENV_VAR: &str = "LVM_MULTI_API_TEST_GOOGLE_ACCESS_TOKEN";
+
+    fn request() -> TextToImageRequest {
+        TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: Some("dog".to_string()),
+            },
+            width: Some(1600),
+            height: Some(900),
+            num_batches: Some(2),
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed: Some(42),
+                ..Default::default()
+            }),
+            imagen: Some(ImagenRequestParameters {
+                person_generation: Some(ImagenPersonGeneration::DontAllow),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn provider(server: &MockServer) -> ImagenProvider {
+        ImagenProvider::from(&ProviderConfiguration {
+            base_url: Some(format!("{}/publishers/google/models", server.uri())),
+            api_key_env_var: Some(TEST_API_KEY_ENV_VAR.to_string()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_to_imagen_body() {
+        assert_eq!(
+            to_imagen_body(&request()),
+            json!({
+                "instances": [{ "prompt": "A painting of a cat" }],
+                "parameters": {
+                    "sampleCount": 2,
+                    "aspectRatio": "16:9",
+                    "negativePrompt": "dog",
+                    "seed": 42,
+                    "personGeneration": "dont_allow",
+                    "addWatermark": false,
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    #[serial(imagen_api_key)]
+    async fn test_text_to_image() -> Result<()> {
+        // SAFETY: Tests that touch this variable are run serially.
+        unsafe { std::env::set_var(TEST_API_KEY_ENV_VAR, "test-token") };
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(
+                "/publishers/google/models/imagen-3.0-generate-002:predict",
+            ))
+            .and(header("authorization", "Bearer test-token"))
+            .and(body_json(to_imagen_body(&request())))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "predictions": [
+                    { "bytesBase64Encoded": "aW1hZ2U=", "mimeType": "image/png" },
+                    { "raiFilteredReason": "Filtered for safety." },
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let images = provider(&server).text_to_image(request()).await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].decode()?, b"image");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial(imagen_api_key)]
+    async fn test_text_to_image_all_filtered() {
+        // SAFETY: Tests that touch this variable are run serially.
+        unsafe { std::env::set_var(TEST_API_KEY_ENV_VAR, "test-token") };
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "predictions": [{ "raiFilteredReason": "Filtered for safety." }]
+            })))
+            .mount(&server)
+            .await;
+
+        let error = provider(&server)
+            .text_to_image(request())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Filtered for safety."));
+        assert_eq!(ErrorKind::of(&error), ErrorKind::ContentRejected);
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_missing_base_url() {
+        let provider = ImagenProvider::from(&ProviderConfiguration::default());
+        let error = provider.text_to_image(request()).await.unwrap_err();
+        assert!(error.downcast_ref::<ProviderConfigurationError>().is_some());
+    }
+}
@@ -1,28 +1,105 @@
 //! Implementations common to all providers.
 
 use crate::{
-    images::LvmImage, parameters::provider::ProviderConfiguration,
-    parameters::text_to_image::TextToImageRequest, traits::TextToImageProvider,
+    images::LvmImage,
+    instrumentation::{event, in_span, metrics},
+    parameters::provider::ProviderConfiguration,
+    parameters::text_to_image::TextToImageRequest,
+    providers::batch::{BatchResults, split_batches},
+    providers::cache::CachedProvider,
+    providers::custom::{CustomProvider, registered_provider},
+    providers::fallback::FallbackProvider,
+    providers::hedged::HedgedProvider,
+    providers::middleware::{Middleware, MiddlewareProvider, inherit_headers},
+    providers::pricing::PricingTable,
+    providers::shared::SharedProvider,
+    providers::usage::MeteredProvider,
+    traits::TextToImageProvider,
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::{sync::Semaphore, task::JoinSet};
 
 #[cfg(feature = "automatic1111")]
-use crate::providers::automatic1111::Automatic1111Provider;
+use crate::providers::automatic1111::{
+    Automatic1111Provider,
+    pool::{Automatic1111PoolConfiguration, Automatic1111PoolProvider},
+};
+#[cfg(feature = "comfyui")]
+use crate::providers::comfyui::ComfyUiProvider;
+#[cfg(feature = "huggingface")]
+use crate::providers::huggingface::HuggingFaceProvider;
+#[cfg(feature = "imagen")]
+use crate::providers::imagen::ImagenProvider;
+#[cfg(feature = "invokeai")]
+use crate::providers::invokeai::InvokeAiProvider;
 #[cfg(feature = "openai")]
 use crate::providers::openai::OpenAiProvider;
-#[cfg(feature = "xai")]
-use crate::providers::xai::XAiProvider;
+#[cfg(feature = "openai-compatible")]
+use crate::providers::openai_compatible::{
+    OpenAiCompatibleConfiguration, OpenAiCompatibleProvider,
+};
+#[cfg(feature = "replicate")]
+use crate::providers::replicate::{ReplicateConfiguration, ReplicateProvider};
+#[cfg(feature = "stability")]
+use crate::providers::stability::StabilityProvider;
+
+/// The default number of requests sent to a provider at the same time when a request is split up.
+pub(crate) const DEFAULT_MAX_CONCURRENT_REQUESTS: u32 = 4;
+
+/// The configuration reported for registered providers before they are looked up.
+const UNRESOLVED_CONFIGURATION: ProviderConfiguration = ProviderConfiguration {
+    base_url: None,
+    api_key_env_var: None,
+    max_concurrent_requests: None,
+};
 
 /// Supported LVM providers.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum LvmProviders {
+    /// A provider implemented outside of this crate.
+    #[serde(skip)]
+    Custom(CustomProvider),
+    /// A provider implemented outside of this crate and registered under this name with
+    /// [`register_provider`](crate::register_provider). It is looked up when a request is sent.
+    Registered(String),
+    /// Several providers tried in order until one of them generates the images.
+    Fallback(FallbackProvider),
+    /// Two providers sent the same request, using whichever generates the images first.
+    Hedged(HedgedProvider),
+    /// A provider whose images are reused for identical requests.
+    Cached(CachedProvider),
+    /// A provider whose usage is counted, with an optional budget.
+    Metered(MeteredProvider),
+    /// A provider whose requests go through a chain of middleware.
+    #[serde(skip)]
+    Middleware(MiddlewareProvider),
+    /// A provider built once and reused by every request, created with [`LvmProviders::shared`].
+    Shared(SharedProvider),
     #[cfg(feature = "openai")]
     OpenAi(ProviderConfiguration),
     #[cfg(feature = "automatic1111")]
     Automatic1111(ProviderConfiguration),
+    /// Several Automatic1111 instances that batches are spread across.
+    #[cfg(feature = "automatic1111")]
+    Automatic1111Pool(Automatic1111PoolConfiguration),
     #[cfg(feature = "xai")]
     XAi(ProviderConfiguration),
+    #[cfg(feature = "stability")]
+    Stability(ProviderConfiguration),
+    #[cfg(feature = "comfyui")]
+    ComfyUi(ProviderConfiguration),
+    #[cfg(feature = "openai-compatible")]
+    OpenAiCompatible(OpenAiCompatibleConfiguration),
+    #[cfg(feature = "invokeai")]
+    InvokeAi(ProviderConfiguration),
+    #[cfg(feature = "replicate")]
+    Replicate(ReplicateConfiguration),
+    #[cfg(feature = "imagen")]
+    Imagen(ProviderConfiguration),
+    #[cfg(feature = "huggingface")]
+    HuggingFace(ProviderConfiguration),
 }
 
 impl Default for LvmProviders {
@@ -34,25 +111,488 @@ impl Default for LvmProviders {
         return LvmProviders::XAi(ProviderConfiguration::default());
         #[cfg(feature = "automatic1111")]
         return LvmProviders::Automatic1111(ProviderConfiguration::default());
+        #[cfg(feature = "stability")]
+        return LvmProviders::Stability(ProviderConfiguration::default());
+        #[cfg(feature = "comfyui")]
+        return LvmProviders::ComfyUi(ProviderConfiguration::default());
+        #[cfg(feature = "openai-compatible")]
+        return LvmProviders::OpenAiCompatible(OpenAiCompatibleConfiguration::default());
+        #[cfg(feature = "invokeai")]
+        return LvmProviders::InvokeAi(ProviderConfiguration::default());
+        #[cfg(feature = "replicate")]
+        return LvmProviders::Replicate(ReplicateConfiguration::default());
+        #[cfg(feature = "imagen")]
+        return LvmProviders::Imagen(ProviderConfiguration::default());
+        #[cfg(feature = "huggingface")]
+        return LvmProviders::HuggingFace(ProviderConfiguration::default());
         panic!("No provider feature enabled");
     }
 }
 
 impl LvmProviders {
+    /// Generate images from a text prompt.
+    /// Requests for more images than the provider can generate in a single call are split into several sub-requests.
+    /// If only some of the sub-requests fail, the images from the successful ones are returned.
     pub async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+
+    /// Generate images from a text prompt, returning the errors of any failed sub-requests alongside the images.
+    /// The size the images are generated at is resolved first and recorded in the image metadata.
+    /// If `resize_mode` is set, images are then resized to exactly the requested size.
+    /// The name of the provider that generated the images is recorded in the image metadata.
+    pub async fn text_to_image_batched(&self, request: TextToImageRequest) -> BatchResults {
+        let start = std::time::Instant::now();
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "text_to_image",
+            provider = self.name(),
+            model = request.model.as_deref(),
+            num_images = request.num_batches.unwrap_or(1),
+        );
+        let results = in_span!(self.dispatch(request), span).await;
+        event!(
+            info,
+            provider = self.name(),
+            latency_ms = start.elapsed().as_millis() as u64,
+            images = results.images.len(),
+            errors = results.errors.len(),
+            "Generated images"
+        );
+        #[cfg(feature = "tracing")]
+        for error in &results.errors {
+            tracing::warn!(provider = self.name(), %error, "Failed to generate images");
+        }
+        metrics::record_request(
+            self.name(),
+            start.elapsed(),
+            results.images.len(),
+            &results.errors,
+        );
+        results
+    }
+
+    /// Send the request to the provider, or to the providers it wraps.
+    async fn dispatch(&self, request: TextToImageRequest) -> BatchResults {
+        if let LvmProviders::Fallback(fallback) = self {
+            return fallback.text_to_image_batched(request).await;
+        }
+        if let LvmProviders::Hedged(hedged) = self {
+            return hedged.text_to_image_batched(request).await;
+        }
+        if let LvmProviders::Cached(cached) = self {
+            return cached.text_to_image_batched(request).await;
+        }
+        if let LvmProviders::Metered(metered) = self {
+            return metered.text_to_image_batched(request).await;
+        }
+        if let LvmProviders::Middleware(middleware) = self {
+            return middleware.text_to_image_batched(request).await;
+        }
+        let mut results = self.generate_batched(request).await;
+        let name = self.name();
+        for image in results.images.iter_mut() {
+            image
+                .metadata_mut()
+                .provider
+                .get_or_insert_with(|| name.to_string());
+        }
+        results
+    }
+
+    /// The name of the provider, as used by the CLI.
+    pub fn name(&self) -> &str {
         match self {
+            LvmProviders::Custom(_) => "custom",
+            LvmProviders::Registered(name) => name,
+            LvmProviders::Fallback(_) => "fallback",
+            LvmProviders::Hedged(_) => "hedged",
+            LvmProviders::Cached(_) => "cached",
+            LvmProviders::Metered(_) => "metered",
+            LvmProviders::Middleware(_) => "middleware",
+            LvmProviders::Shared(shared) => shared.provider().name(),
             #[cfg(feature = "openai")]
-            LvmProviders::OpenAi(config) => {
-                OpenAiProvider::from(config).text_to_image(request).await
+            LvmProviders::OpenAi(_) => "open-ai",
+            #[cfg(feature = "automatic1111")]
+            LvmProviders::Automatic1111(_) => "automatic1111",
+            #[cfg(feature = "automatic1111")]
+            LvmProviders::Automatic1111Pool(_) => "automatic1111-pool",
+            #[cfg(feature = "xai")]
+            LvmProviders::XAi(_) => "x-ai",
+            #[cfg(feature = "stability")]
+            LvmProviders::Stability(_) => "stability",
+            #[cfg(feature = "comfyui")]
+            LvmProviders::ComfyUi(_) => "comfy-ui",
+            #[cfg(feature = "openai-compatible")]
+            LvmProviders::OpenAiCompatible(_) => "open-ai-compatible",
+            #[cfg(feature = "invokeai")]
+            LvmProviders::InvokeAi(_) => "invoke-ai",
+            #[cfg(feature = "replicate")]
+            LvmProviders::Replicate(_) => "replicate",
+            #[cfg(feature = "imagen")]
+            LvmProviders::Imagen(_) => "imagen",
+            #[cfg(feature = "huggingface")]
+            LvmProviders::HuggingFace(_) => "hugging-face",
+        }
+    }
+
+    /// Build the provider once and wrap it, so requests sent through the result and its clones share the same
+    /// provider instance. The result keeps the name and configuration of the provider.
+    /// Registered providers are looked up here.
+    pub fn shared(&self) -> Result<LvmProviders> {
+        match self {
+            LvmProviders::Custom(_)
+            | LvmProviders::Fallback(_)
+            | LvmProviders::Hedged(_)
+            | LvmProviders::Cached(_)
+            | LvmProviders::Metered(_)
+            | LvmProviders::Middleware(_)
+            | LvmProviders::Shared(_) => Ok(self.clone()),
+            _ => {
+                let shared = SharedProvider::new(self.clone());
+                shared.build()?;
+                Ok(LvmProviders::Shared(shared))
+            }
+        }
+    }
+
+    async fn generate_batched(&self, mut request: TextToImageRequest) -> BatchResults {
+        let CustomProvider {
+            provider,
+            configuration,
+        } = match self.build_configured() {
+            Ok(built) => built,
+            Err(error) => {
+                return BatchResults {
+                    images: Vec::new(),
+                    errors: vec![error],
+                };
+            }
+        };
+
+        #[cfg(not(feature = "image"))]
+        if request.resize_mode.is_some() {
+            return BatchResults {
+                images: Vec::new(),
+                errors: vec![anyhow::anyhow!(
+                    "Resizing images requires the `image` feature."
+                )],
+            };
+        }
+        #[cfg(feature = "image")]
+        let (requested_size, resize_mode) = (
+            request.image_size().and_then(|size| size.pixels()),
+            request.resize_mode,
+        );
+        // Sizes the provider can't generate exactly are fixed up afterwards, so settle for the closest one.
+        #[cfg(feature = "image")]
+        if let (Some(_), Some(crate::ImageSize::Exact { width, height })) =
+            (resize_mode, request.size)
+        {
+            request.size = Some(crate::ImageSize::Closest { width, height });
+        }
+
+        let resolved_size = match resolve_request_size(provider.as_ref(), &request) {
+            Ok(size) => size,
+            Err(error) => {
+                return BatchResults {
+                    images: Vec::new(),
+                    errors: vec![error],
+                };
             }
+        };
+        if let Some((width, height)) = resolved_size {
+            request.width = Some(width);
+            request.height = Some(height);
+        }
+
+        let mut results = match self {
+            // The pool returns the batches that failed alongside the images of the others.
             #[cfg(feature = "automatic1111")]
-            LvmProviders::Automatic1111(config) => {
-                Automatic1111Provider::from(config)
-                    .text_to_image(request)
+            LvmProviders::Automatic1111Pool(config) => {
+                Automatic1111PoolProvider::from(config)
+                    .text_to_image_batched(request)
                     .await
             }
+            _ => text_to_image_split(provider, &configuration, request).await,
+        };
+        if let Some((width, height)) = resolved_size {
+            for image in results.images.iter_mut() {
+                let metadata = image.metadata_mut();
+                metadata.width = Some(width);
+                metadata.height = Some(height);
+            }
+        }
+
+        #[cfg(feature = "image")]
+        if let (Some(mode), Some((width, height))) = (resize_mode, requested_size) {
+            let images = std::mem::take(&mut results.images);
+            for mut image in images {
+                match image.resize_to(width, height, mode) {
+                    Ok(()) => results.images.push(image),
+                    Err(error) => results.errors.push(error),
+                }
+            }
+        }
+        results
+    }
+
+    /// The maximum number of images the provider can generate in a single call.
+    /// `None` if the provider handles any number of batches itself.
+    pub fn max_images_per_request(&self, request: &TextToImageRequest) -> Option<u32> {
+        self.build().ok()?.max_images_per_request(request)
+    }
+
+    /// Pick the size closest to the requested size that the provider supports.
+    /// `None` if no size was requested, in which case the provider uses its default size.
+    pub fn resolve_size(&self, request: &TextToImageRequest) -> Result<Option<(u32, u32)>> {
+        resolve_request_size(self.build()?.as_ref(), request)
+    }
+
+    /// Run the provider's requests through middleware, after any middleware it already has.
+    pub fn with_middleware(self, middleware: impl Middleware + 'static) -> LvmProviders {
+        match self {
+            LvmProviders::Middleware(chain) => LvmProviders::Middleware(chain.with(middleware)),
+            provider => {
+                LvmProviders::Middleware(MiddlewareProvider::new(provider).with(middleware))
+            }
+        }
+    }
+
+    /// Estimate what a request costs in US dollars with the default pricing table, or `None` if the provider isn't priced.
+    pub fn estimate_cost(&self, request: &TextToImageRequest) -> Result<Option<f64>> {
+        PricingTable::default().estimate(self, request)
+    }
+
+    /// The configuration of the provider.
+    /// Registered providers report an empty configuration, since they are only looked up when a request is sent,
+    /// and requests then use the configuration they were registered with.
+    /// Providers that wrap others, like fallback chains, do too, since the providers they wrap have their own configuration.
+    pub fn configuration(&self) -> &ProviderConfiguration {
+        match self {
+            LvmProviders::Custom(custom) => &custom.configuration,
+            LvmProviders::Shared(shared) => shared.provider().configuration(),
+            LvmProviders::Registered(_)
+            | LvmProviders::Fallback(_)
+            | LvmProviders::Hedged(_)
+            | LvmProviders::Cached(_)
+            | LvmProviders::Metered(_)
+            | LvmProviders::Middleware(_) => &UNRESOLVED_CONFIGURATION,
+            #[cfg(feature = "openai")]
+            LvmProviders::OpenAi(config) => config,
+            #[cfg(feature = "automatic1111")]
+            LvmProviders::Automatic1111(config) => config,
+            #[cfg(feature = "automatic1111")]
+            LvmProviders::Automatic1111Pool(config) => &config.provider,
             #[cfg(feature = "xai")]
-            LvmProviders::XAi(config) => XAiProvider::from(config).text_to_image(request).await,
+            LvmProviders::XAi(config) => config,
+            #[cfg(feature = "stability")]
+            LvmProviders::Stability(config) => config,
+            #[cfg(feature = "comfyui")]
+            LvmProviders::ComfyUi(config) => config,
+            #[cfg(feature = "openai-compatible")]
+            LvmProviders::OpenAiCompatible(config) => &config.provider,
+            #[cfg(feature = "invokeai")]
+            LvmProviders::InvokeAi(config) => config,
+            #[cfg(feature = "replicate")]
+            LvmProviders::Replicate(config) => &config.provider,
+            #[cfg(feature = "imagen")]
+            LvmProviders::Imagen(config) => config,
+            #[cfg(feature = "huggingface")]
+            LvmProviders::HuggingFace(config) => config,
         }
     }
+
+    /// Build the concrete provider along with the configuration used when sending it requests.
+    /// Registered providers are looked up once, so both come from the same registration.
+    pub(crate) fn build_configured(&self) -> Result<CustomProvider> {
+        match self {
+            LvmProviders::Registered(name) => {
+                registered_provider(name).ok_or_else(|| unregistered_error(name))
+            }
+            LvmProviders::Shared(shared) => shared.build_configured(),
+            _ => Ok(CustomProvider {
+                provider: self.build()?,
+                configuration: self.configuration().clone(),
+            }),
+        }
+    }
+
+    /// Build the concrete provider.
+    /// The provider can be cloned cheaply and shared between tasks.
+    pub fn build(&self) -> Result<Arc<dyn TextToImageProvider>> {
+        let provider: Arc<dyn TextToImageProvider> = match self {
+            LvmProviders::Custom(custom) => Arc::clone(&custom.provider),
+            LvmProviders::Registered(_) => self.build_configured()?.provider,
+            LvmProviders::Fallback(fallback) => Arc::new(fallback.clone()),
+            LvmProviders::Hedged(hedged) => Arc::new(hedged.clone()),
+            LvmProviders::Cached(cached) => Arc::new(cached.clone()),
+            LvmProviders::Metered(metered) => Arc::new(metered.clone()),
+            LvmProviders::Middleware(middleware) => Arc::new(middleware.clone()),
+            LvmProviders::Shared(shared) => shared.build()?,
+            #[cfg(feature = "openai")]
+            LvmProviders::OpenAi(config) => Arc::new(OpenAiProvider::from(config)),
+            #[cfg(feature = "automatic1111")]
+            LvmProviders::Automatic1111(config) => Arc::new(Automatic1111Provider::from(config)),
+            #[cfg(feature = "automatic1111")]
+            LvmProviders::Automatic1111Pool(config) => {
+                Arc::new(Automatic1111PoolProvider::from(config))
+            }
+            #[cfg(feature = "xai")]
+            LvmProviders::XAi(config) => Arc::new(OpenAiCompatibleProvider::from(
+                &crate::providers::xai::configuration(config),
+            )),
+            #[cfg(feature = "stability")]
+            LvmProviders::Stability(config) => Arc::new(StabilityProvider::from(config)),
+            #[cfg(feature = "comfyui")]
+            LvmProviders::ComfyUi(config) => Arc::new(ComfyUiProvider::from(config)),
+            #[cfg(feature = "openai-compatible")]
+            LvmProviders::OpenAiCompatible(config) => {
+                Arc::new(OpenAiCompatibleProvider::from(config))
+            }
+            #[cfg(feature = "invokeai")]
+            LvmProviders::InvokeAi(config) => Arc::new(InvokeAiProvider::from(config)),
+            #[cfg(feature = "replicate")]
+            LvmProviders::Replicate(config) => Arc::new(ReplicateProvider::from(config)),
+            #[cfg(feature = "imagen")]
+            LvmProviders::Imagen(config) => Arc::new(ImagenProvider::from(config)),
+            #[cfg(feature = "huggingface")]
+            LvmProviders::HuggingFace(config) => Arc::new(HuggingFaceProvider::from(config)),
+        };
+        Ok(provider)
+    }
+}
+
+/// Resolve the size requested by a request with a provider.
+fn resolve_request_size(
+    provider: &dyn TextToImageProvider,
+    request: &TextToImageRequest,
+) -> Result<Option<(u32, u32)>> {
+    let Some(size) = request.image_size() else {
+        return Ok(None);
+    };
+    Ok(Some(provider.resolve_size(request, &size)?))
+}
+
+/// Split the request into as many sub-requests as the provider needs and send them,
+/// with at most the configured `max_concurrent_requests` running at once.
+async fn text_to_image_split(
+    provider: Arc<dyn TextToImageProvider>,
+    configuration: &ProviderConfiguration,
+    request: TextToImageRequest,
+) -> BatchResults {
+    let Some(max_per_request) = provider.max_images_per_request(&request) else {
+        return match provider.text_to_image(request).await {
+            Ok(images) => BatchResults {
+                images,
+                errors: Vec::new(),
+            },
+            Err(error) => BatchResults {
+                images: Vec::new(),
+                errors: vec![error],
+            },
+        };
+    };
+
+    let num_images = request.num_batches.unwrap_or(1).max(1);
+    let max_concurrent_requests = configuration
+        .max_concurrent_requests
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS)
+        .max(1);
+    let semaphore = Arc::new(Semaphore::new(max_concurrent_requests as usize));
+
+    // Dropping the set aborts the sub-requests, so they don't outlive the request.
+    let mut tasks = JoinSet::new();
+    let batches = split_batches(num_images, max_per_request);
+    for (index, num_batches) in batches.iter().enumerate() {
+        let provider = Arc::clone(&provider);
+        let semaphore = Arc::clone(&semaphore);
+        let request = TextToImageRequest {
+            num_batches: Some(*num_batches),
+            ..request.clone()
+        };
+        tasks.spawn(inherit_headers(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            provider
+                .text_to_image(request)
+                .await
+                .map(|images| (index, images))
+        }));
+    }
+
+    // Images are returned in the order of the sub-requests, whatever order they finish in.
+    let mut images = vec![Vec::new(); batches.len()];
+    let mut results = BatchResults::default();
+    while let Some(result) = tasks.join_next().await {
+        match result.map_err(anyhow::Error::from).and_then(|r| r) {
+            Ok((index, batch)) => images[index] = batch,
+            Err(error) => results.errors.push(error),
+        }
+    }
+    results.images = images.into_iter().flatten().collect();
+    results
+}
+
+fn unregistered_error(name: &str) -> anyhow::Error {
+    anyhow!("No provider is registered under the name {:?}.", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_max_images_per_request_dall_e_3() {
+        let provider = LvmProviders::OpenAi(ProviderConfiguration::default());
+        let request = TextToImageRequest {
+            model: Some("dall-e-3".to_string()),
+            num_batches: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(provider.max_images_per_request(&request), Some(1));
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_resolve_size_openai() -> Result<()> {
+        let provider = LvmProviders::OpenAi(ProviderConfiguration::default());
+        let request = TextToImageRequest {
+            model: Some("dall-e-3".to_string()),
+            size: Some(crate::ImageSize::AspectRatio {
+                width: 16,
+                height: 9,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(provider.resolve_size(&request)?, Some((1792, 1024)));
+        assert_eq!(provider.resolve_size(&TextToImageRequest::default())?, None);
+        Ok(())
+    }
+
+    #[cfg(feature = "automatic1111")]
+    #[test]
+    fn test_max_images_per_request_automatic1111() {
+        let provider = LvmProviders::Automatic1111(ProviderConfiguration::default());
+        let request = TextToImageRequest::default();
+        assert_eq!(provider.max_images_per_request(&request), None);
+    }
+
+    #[cfg(feature = "xai")]
+    #[test]
+    fn test_shared() -> Result<()> {
+        let provider = LvmProviders::XAi(ProviderConfiguration {
+            max_concurrent_requests: Some(2),
+            ..Default::default()
+        })
+        .shared()?;
+        assert!(Arc::ptr_eq(&provider.build()?, &provider.clone().build()?));
+        assert_eq!(provider.name(), "x-ai");
+        assert_eq!(provider.configuration().max_concurrent_requests, Some(2));
+        assert_eq!(
+            provider.max_images_per_request(&TextToImageRequest::default()),
+            Some(10)
+        );
+        Ok(())
+    }
 }
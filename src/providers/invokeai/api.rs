@@ -0,0 +1,149 @@
+//! Endpoints for looking up models, enqueueing graphs and fetching their outputs.
+
+use super::{InvokeAiProvider, graph::ModelIdentifier};
+use crate::instrumentation::{self, SendTraced, event, in_span};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// InvokeAI has a single session queue.
+const QUEUE_ID: &str = "default";
+
+pub(crate) type ItemId = u64;
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    models: Vec<ModelIdentifier>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EnqueueBatchResponse {
+    item_ids: Vec<ItemId>,
+}
+
+/// An item in the session queue.
+#[derive(Debug, Deserialize)]
+pub(crate) struct QueueItem {
+    /// One of `pending`, `in_progress`, `completed`, `failed` or `canceled`.
+    pub status: String,
+    pub error_message: Option<String>,
+    pub session: Session,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Session {
+    /// The outputs of each node that has been executed.
+    #[serde(default)]
+    pub results: HashMap<String, NodeOutput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct NodeOutput {
+    #[serde(rename = "type")]
+    pub output_type: String,
+    pub image: Option<ImageField>,
+}
+
+/// A reference to an image stored by InvokeAI.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ImageField {
+    pub image_name: String,
+}
+
+impl QueueItem {
+    /// Whether the item has stopped running, successfully or not.
+    pub(crate) fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "completed" | "failed" | "canceled")
+    }
+}
+
+impl InvokeAiProvider {
+    /// Send a GET request to `/api/v2/models/` to list the installed main models.
+    pub(crate) async fn get_models(&self) -> Result<Vec<ModelIdentifier>> {
+        let url = format!("{}/api/v2/models/", self.base_url);
+        let response: ModelsResponse = reqwest::Client::new()
+            .get(&url)
+            .query(&[("model_type", "main")])
+            .send_traced()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.models)
+    }
+
+    /// Send a POST request to `/api/v1/queue/{queue_id}/enqueue_batch` to enqueue a graph once for each seed.
+    /// The response contains the IDs of the queue items used to track each run.
+    pub(crate) async fn enqueue_batch(&self, graph: &Value, seeds: &[u32]) -> Result<Vec<ItemId>> {
+        let url = format!("{}/api/v1/queue/{}/enqueue_batch", self.base_url, QUEUE_ID);
+        let body = json!({
+            "prepend": false,
+            "batch": {
+                "graph": graph,
+                "runs": 1,
+                "data": [[{
+                    "node_path": super::graph::NOISE_NODE,
+                    "field_name": "seed",
+                    "items": seeds,
+                }]],
+            },
+        });
+        let response = reqwest::Client::new()
+            .post(&url)
+            .json(&body)
+            .send_traced()
+            .await?;
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let response = response.text().await?;
+            return Err(anyhow!(
+                "Failed to enqueue batch. Request URL: {:?}, Response: {:?}",
+                &url,
+                response
+            ));
+        }
+        let response: EnqueueBatchResponse = response.json().await?;
+        Ok(response.item_ids)
+    }
+
+    /// Send a GET request to `/api/v1/queue/{queue_id}/i/{item_id}`.
+    pub(crate) async fn get_queue_item(&self, item_id: ItemId) -> Result<QueueItem> {
+        let url = format!("{}/api/v1/queue/{}/i/{}", self.base_url, QUEUE_ID, item_id);
+        Ok(instrumentation::get(&url)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Send a GET request to `/api/v1/images/i/{image_name}/full` to download an image.
+    pub(crate) async fn get_image(&self, image: &ImageField) -> Result<Vec<u8>> {
+        let url = format!(
+            "{}/api/v1/images/i/{}/full",
+            self.base_url, image.image_name
+        );
+        let response = instrumentation::get(&url).await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Poll a queue item until it has finished.
+    pub(crate) async fn poll_queue_item(&self, item_id: ItemId) -> Result<QueueItem> {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let item = in_span!(
+                async {
+                    let item = self.get_queue_item(item_id).await?;
+                    event!(debug, status = %item.status, "Polled queue item");
+                    anyhow::Ok(item)
+                },
+                tracing::debug_span!("poll_queue_item", item_id)
+            )
+            .await?;
+            if item.is_finished() {
+                return Ok(item);
+            }
+        }
+    }
+}
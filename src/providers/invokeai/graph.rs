@@ -0,0 +1,192 @@
+//! Text-to-image graphs enqueued on InvokeAI.
+//!
+//! A graph is a set of invocation nodes together with the edges that connect their outputs to other nodes' inputs.
+
+use crate::parameters::text_to_image::TextToImageRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// The ID of the node that generates the noise, whose seed is varied between the images of a batch.
+pub(crate) const NOISE_NODE: &str = "noise";
+
+const DEFAULT_STEPS: u32 = 30;
+const DEFAULT_CFG_SCALE: f64 = 7.5;
+const DEFAULT_SCHEDULER: &str = "euler";
+
+/// A model installed on an InvokeAI instance.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub(crate) struct ModelIdentifier {
+    pub key: String,
+    pub hash: String,
+    pub name: String,
+    pub base: String,
+    #[serde(rename = "type")]
+    pub model_type: String,
+}
+
+impl ModelIdentifier {
+    fn is_sdxl(&self) -> bool {
+        self.base == "sdxl"
+    }
+
+    /// The size the model was trained on, used if the request doesn't set one.
+    fn default_size(&self) -> u32 {
+        if self.is_sdxl() { 1024 } else { 512 }
+    }
+}
+
+/// Connect the output of a node to the input of another node.
+fn edge(source: &str, output: &str, destination: &str, input: &str) -> Value {
+    json!({
+        "source": { "node_id": source, "field": output },
+        "destination": { "node_id": destination, "field": input },
+    })
+}
+
+/// Build a text-to-image graph for a request.
+/// SDXL models use the SDXL loader and prompt nodes, while all other models use the Stable Diffusion 1.x nodes.
+/// `sampler_name` is passed as InvokeAI's scheduler, e.g. `euler_a` or `dpmpp_2m_k`.
+pub(crate) fn text_to_image_graph(
+    request: &TextToImageRequest,
+    model: &ModelIdentifier,
+    seed: u32,
+) -> Value {
+    let extended = request.extended.clone().unwrap_or_default();
+    let width = request.width.unwrap_or(model.default_size());
+    let height = request.height.unwrap_or(model.default_size());
+    let positive_prompt = request.prompt.positive_prompt.clone().unwrap_or_default();
+    let negative_prompt = request.prompt.negative_prompt.clone().unwrap_or_default();
+
+    let (loader, prompt) = if model.is_sdxl() {
+        ("sdxl_model_loader", "sdxl_compel_prompt")
+    } else {
+        ("main_model_loader", "compel")
+    };
+    let prompt_node = |id: &str, text: &str| {
+        let mut node = json!({ "id": id, "type": prompt, "prompt": text });
+        if model.is_sdxl() {
+            node["style"] = text.into();
+            node["original_width"] = width.into();
+            node["original_height"] = height.into();
+            node["target_width"] = width.into();
+            node["target_height"] = height.into();
+            node["crop_top"] = 0.into();
+            node["crop_left"] = 0.into();
+        }
+        node
+    };
+
+    let mut edges = vec![
+        edge("model_loader", "unet", "denoise", "unet"),
+        edge("model_loader", "clip", "positive", "clip"),
+        edge("model_loader", "clip", "negative", "clip"),
+        edge("model_loader", "vae", "l2i", "vae"),
+        edge(
+            "positive",
+            "conditioning",
+            "denoise",
+            "positive_conditioning",
+        ),
+        edge(
+            "negative",
+            "conditioning",
+            "denoise",
+            "negative_conditioning",
+        ),
+        edge(NOISE_NODE, "noise", "denoise", "noise"),
+        edge("denoise", "latents", "l2i", "latents"),
+    ];
+    if model.is_sdxl() {
+        edges.push(edge("model_loader", "clip2", "positive", "clip2"));
+        edges.push(edge("model_loader", "clip2", "negative", "clip2"));
+    }
+
+    json!({
+        "id": "lvm_multi_api_text_to_image",
+        "nodes": {
+            "model_loader": { "id": "model_loader", "type": loader, "model": model },
+            "positive": prompt_node("positive", &positive_prompt),
+            "negative": prompt_node("negative", &negative_prompt),
+            NOISE_NODE: {
+                "id": NOISE_NODE,
+                "type": "noise",
+                "seed": seed,
+                "width": width,
+                "height": height,
+                "use_cpu": true,
+            },
+            "denoise": {
+                "id": "denoise",
+                "type": "denoise_latents",
+                "steps": extended.steps.unwrap_or(DEFAULT_STEPS),
+                "cfg_scale": extended.cfg_scale.unwrap_or(DEFAULT_CFG_SCALE),
+                "scheduler": extended.sampler_name.unwrap_or(DEFAULT_SCHEDULER.to_string()),
+                "denoising_start": 0.0,
+                "denoising_end": 1.0,
+            },
+            // Only the decoded image is kept in the gallery.
+            "l2i": { "id": "l2i", "type": "l2i", "is_intermediate": false },
+        },
+        "edges": edges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        prompt::ImagePrompt, text_to_image::TextToImageRequestExtendedParameters,
+    };
+
+    fn model(base: &str) -> ModelIdentifier {
+        ModelIdentifier {
+            key: "1234".to_string(),
+            hash: "blake3:abcd".to_string(),
+            name: "model".to_string(),
+            base: base.to_string(),
+            model_type: "main".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_text_to_image_graph() {
+        let request = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: Some("dog".to_string()),
+            },
+            width: Some(768),
+            extended: Some(TextToImageRequestExtendedParameters {
+                steps: Some(10),
+                cfg_scale: Some(3.5),
+                sampler_name: Some("dpmpp_2m_k".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let graph = text_to_image_graph(&request, &model("sd-1"), 42);
+        let nodes = &graph["nodes"];
+        assert_eq!(nodes["model_loader"]["type"], "main_model_loader");
+        assert_eq!(nodes["model_loader"]["model"]["key"], "1234");
+        assert_eq!(nodes["positive"]["prompt"], "A painting of a cat");
+        assert_eq!(nodes["negative"]["prompt"], "dog");
+        assert_eq!(nodes["noise"]["seed"], 42);
+        assert_eq!(nodes["noise"]["width"], 768);
+        assert_eq!(nodes["noise"]["height"], 512);
+        assert_eq!(nodes["denoise"]["steps"], 10);
+        assert_eq!(nodes["denoise"]["cfg_scale"], 3.5);
+        assert_eq!(nodes["denoise"]["scheduler"], "dpmpp_2m_k");
+        assert_eq!(graph["edges"].as_array().map(Vec::len), Some(8));
+    }
+
+    #[test]
+    fn test_text_to_image_graph_sdxl() {
+        let graph = text_to_image_graph(&TextToImageRequest::default(), &model("sdxl"), 42);
+        let nodes = &graph["nodes"];
+        assert_eq!(nodes["model_loader"]["type"], "sdxl_model_loader");
+        assert_eq!(nodes["positive"]["type"], "sdxl_compel_prompt");
+        assert_eq!(nodes["positive"]["target_width"], 1024);
+        assert_eq!(nodes["denoise"]["steps"], DEFAULT_STEPS);
+        assert_eq!(graph["edges"].as_array().map(Vec::len), Some(10));
+    }
+}
@@ -0,0 +1,280 @@
+mod api;
+mod graph;
+
+use crate::{
+    images::{LvmImage, LvmImageMetadata},
+    parameters::{
+        provider::ProviderConfiguration, size::ImageSize, text_to_image::TextToImageRequest,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use api::QueueItem;
+use async_trait::async_trait;
+use base64::Engine;
+use graph::ModelIdentifier;
+
+const DEFAULT_BASE_URL: &str = "http://127.0.0.1:9090";
+
+/// How long to wait for all the images of a request to finish.
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// A provider for generating images with an InvokeAI instance.
+/// Requests are built into a text-to-image graph which is enqueued on the instance's session queue.
+#[derive(Debug, Clone)]
+pub struct InvokeAiProvider {
+    pub base_url: String,
+}
+
+impl Default for InvokeAiProvider {
+    fn default() -> Self {
+        Self::from(&ProviderConfiguration::default())
+    }
+}
+
+impl From<&ProviderConfiguration> for InvokeAiProvider {
+    fn from(config: &ProviderConfiguration) -> Self {
+        InvokeAiProvider {
+            base_url: config
+                .base_url
+                .clone()
+                .unwrap_or(DEFAULT_BASE_URL.to_string()),
+        }
+    }
+}
+
+impl InvokeAiProvider {
+    /// Find an installed main model by name or key.
+    /// If no model is given, the first installed model is used.
+    async fn find_model(&self, model: Option<&str>) -> Result<ModelIdentifier> {
+        let models = self.get_models().await?;
+        match model {
+            Some(model) => models
+                .into_iter()
+                .find(|m| m.name == model || m.key == model)
+                .ok_or_else(|| anyhow!("Model {:?} is not installed.", model)),
+            None => models
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No models are installed.")),
+        }
+    }
+
+    /// Download the image produced by a finished queue item.
+    async fn download_output(&self, item: QueueItem) -> Result<Vec<u8>> {
+        if item.status != "completed" {
+            return Err(anyhow!(
+                "Queue item did not complete. Status: {}, Error: {}",
+                item.status,
+                item.error_message.unwrap_or_default()
+            ));
+        }
+        let image = item
+            .session
+            .results
+            .into_values()
+            .filter(|output| output.output_type == "image_output")
+            .find_map(|output| output.image)
+            .ok_or_else(|| anyhow!("Queue item did not produce an image."))?;
+        self.get_image(&image).await
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for InvokeAiProvider {
+    /// Generate images by enqueueing one run of a text-to-image graph for each batch.
+    /// Each run uses the next seed after the previous one, starting from the request's seed or a random seed.
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        let model = self.find_model(request.model.as_deref()).await?;
+        let first_seed = request
+            .extended
+            .as_ref()
+            .and_then(|extended| extended.seed)
+            .unwrap_or(super::random_seed() as u32);
+        let seeds: Vec<u32> = (0..request.num_batches.unwrap_or(1).max(1))
+            .map(|i| first_seed.wrapping_add(i))
+            .collect();
+
+        let mut graph = graph::text_to_image_graph(&request, &model, first_seed);
+        let item_ids = self.enqueue_batch(&graph, &seeds).await?;
+
+        tokio::time::timeout(TIMEOUT, async {
+            let mut images = Vec::new();
+            for (item_id, seed) in item_ids.into_iter().zip(seeds) {
+                let item = self.poll_queue_item(item_id).await?;
+                let image = self.download_output(item).await?;
+                graph["nodes"][graph::NOISE_NODE]["seed"] = seed.into();
+                images.push(LvmImage {
+                    data: base64::prelude::BASE64_STANDARD
+                        .encode(image)
+                        .as_bytes()
+                        .to_vec(),
+                    metadata: Some(LvmImageMetadata {
+                        generation_params: serde_json::to_string(&graph).ok(),
+                        ..Default::default()
+                    }),
+                });
+            }
+            Ok(images)
+        })
+        .await
+        .map_err(|_| anyhow!("Request to InvokeAI timed out."))?
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        crate::providers::automatic1111::resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        prompt::ImagePrompt, text_to_image::TextToImageRequestExtendedParameters,
+    };
+    use serde_json::json;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_partial_json, method, path, query_param},
+    };
+
+    async fn mount_models(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path("/api/v2/models/"))
+            .and(query_param("model_type", "main"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "models": [{
+                    "key": "1234",
+                    "hash": "blake3:abcd",
+                    "name": "stable-diffusion-v1-5",
+                    "base": "sd-1",
+                    "type": "main",
+                    "format": "diffusers",
+                }]
+            })))
+            .mount(server)
+            .await;
+    }
+
+    fn queue_item(status: &str, image_name: &str) -> serde_json::Value {
+        json!({
+            "item_id": 1,
+            "status": status,
+            "error_message": null,
+            "session": {
+                "id": "session",
+                "results": {
+                    "noise-1": { "type": "noise_output", "width": 512, "height": 512 },
+                    "l2i-1": {
+                        "type": "image_output",
+                        "image": { "image_name": image_name },
+                        "width": 512,
+                        "height": 512,
+                    },
+                },
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image() -> Result<()> {
+        let server = MockServer::start().await;
+        mount_models(&server).await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/queue/default/enqueue_batch"))
+            .and(body_partial_json(json!({
+                "batch": {
+                    "graph": { "nodes": {
+                        "positive": { "prompt": "A painting of a cat" },
+                        "model_loader": { "model": { "key": "1234" } },
+                    } },
+                    "data": [[{ "node_path": "noise", "field_name": "seed", "items": [7, 8] }]],
+                }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "enqueued": 2,
+                "item_ids": [1, 2],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for (item_id, image_name) in [(1, "first.png"), (2, "second.png")] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/v1/queue/default/i/{}", item_id)))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(queue_item("completed", image_name)),
+                )
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/api/v1/images/i/{}/full", image_name)))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(image_name.as_bytes()))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let provider = InvokeAiProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        });
+        let request = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: None,
+            },
+            model: Some("stable-diffusion-v1-5".to_string()),
+            num_batches: Some(2),
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed: Some(7),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let images = provider.text_to_image(request).await?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].decode()?, b"first.png");
+        assert_eq!(images[1].decode()?, b"second.png");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_failed() {
+        let server = MockServer::start().await;
+        mount_models(&server).await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/queue/default/enqueue_batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "item_ids": [1] })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/queue/default/i/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(queue_item("failed", "")))
+            .mount(&server)
+            .await;
+
+        let provider = InvokeAiProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        });
+        let result = provider.text_to_image(TextToImageRequest::default()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_unknown_model() {
+        let server = MockServer::start().await;
+        mount_models(&server).await;
+
+        let provider = InvokeAiProvider::from(&ProviderConfiguration {
+            base_url: Some(server.uri()),
+            ..Default::default()
+        });
+        let request = TextToImageRequest {
+            model: Some("sd_xl_base_1.0".to_string()),
+            ..Default::default()
+        };
+        let error = provider.text_to_image(request).await.unwrap_err();
+        assert!(error.to_string().contains("not installed"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Hooks run before requests are sent to a provider and after it responds.
+//!
+//! Middleware can change the request, add HTTP headers, reject the request, or inspect and change the images.
+//! Headers are added to the HTTP requests this crate sends itself,
+//! so they aren't sent by `OpenAiProvider`, which uses `async-openai`, or by providers implemented outside of this crate.
+
+use crate::{
+    images::LvmImage,
+    parameters::text_to_image::TextToImageRequest,
+    providers::{batch::BatchResults, index::LvmProviders},
+    traits::TextToImageProvider,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use std::{future::Future, pin::Pin, sync::Arc};
+
+tokio::task_local! {
+    /// The headers added by the middleware the current request went through.
+    static HEADERS: HeaderMap;
+}
+
+/// A request on its way to a provider.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// The name of the provider the request is sent to, as returned by [`LvmProviders::name`].
+    pub provider: String,
+    pub request: TextToImageRequest,
+    /// Headers added to the HTTP requests sent to the provider.
+    pub headers: HeaderMap,
+}
+
+/// Hooks run around the requests sent through a [`MiddlewareProvider`].
+/// Both hooks do nothing by default, so middleware only needs to implement the ones it uses.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Called before the request is sent, in the order the middleware was added.
+    /// Return an error to reject the request without sending it.
+    async fn before_request(&self, _context: &mut RequestContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called with the images the provider generated, in the reverse order the middleware was added.
+    /// Return an error to discard the images and fail the request instead.
+    async fn after_response(
+        &self,
+        _context: &RequestContext,
+        _images: &mut Vec<LvmImage>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Wraps a provider, running its requests through a chain of middleware.
+#[derive(Clone)]
+pub struct MiddlewareProvider {
+    pub provider: Box<LvmProviders>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl MiddlewareProvider {
+    pub fn new(provider: LvmProviders) -> Self {
+        MiddlewareProvider {
+            provider: Box::new(provider),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Add middleware to the end of the chain.
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Run the request through the middleware and send it to the provider.
+    /// If a hook fails, the request fails with its error.
+    pub fn text_to_image_batched(
+        &self,
+        request: TextToImageRequest,
+    ) -> Pin<Box<dyn Future<Output = BatchResults> + Send + '_>> {
+        // Boxed, since the provider can itself have middleware.
+        Box::pin(async move {
+            let mut context = RequestContext {
+                provider: self.provider.name().to_string(),
+                request,
+                headers: HeaderMap::new(),
+            };
+            for middleware in &self.middleware {
+                if let Err(error) = middleware.before_request(&mut context).await {
+                    return BatchResults {
+                        images: Vec::new(),
+                        errors: vec![error],
+                    };
+                }
+            }
+
+            // Headers added by middleware further out are kept, unless they are replaced.
+            let mut headers = HEADERS.try_with(HeaderMap::clone).unwrap_or_default();
+            headers.extend(context.headers.clone());
+            let mut results = HEADERS
+                .scope(
+                    headers,
+                    self.provider.text_to_image_batched(context.request.clone()),
+                )
+                .await;
+
+            for middleware in self.middleware.iter().rev() {
+                if let Err(error) = middleware
+                    .after_response(&context, &mut results.images)
+                    .await
+                {
+                    results.images.clear();
+                    results.errors.push(error);
+                    break;
+                }
+            }
+            results
+        })
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for MiddlewareProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+}
+
+impl std::fmt::Debug for MiddlewareProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiddlewareProvider")
+            .field("provider", &self.provider)
+            .field("middleware", &self.middleware.len())
+            .finish()
+    }
+}
+
+impl PartialEq for MiddlewareProvider {
+    /// Middleware providers are equal if they wrap equal providers with the same middleware instances.
+    fn eq(&self, other: &Self) -> bool {
+        self.provider == other.provider
+            && self.middleware.len() == other.middleware.len()
+            && self
+                .middleware
+                .iter()
+                .zip(&other.middleware)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+/// The headers added by middleware to the request the current task is sending.
+pub(crate) fn current_headers() -> Option<HeaderMap> {
+    HEADERS.try_with(HeaderMap::clone).ok()
+}
+
+/// Keep the headers added by middleware in a future that is spawned as a separate task.
+pub(crate) fn inherit_headers<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let headers = current_headers();
+    async move {
+        match headers {
+            Some(headers) => HEADERS.scope(headers, future).await,
+            None => future.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instrumentation, parameters::prompt::ImagePrompt, providers::custom::CustomProvider,
+    };
+    use anyhow::anyhow;
+    use reqwest::header::HeaderValue;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{header, method},
+    };
+
+    /// Sends a GET request to a server for each image, and returns the prompt as the image.
+    struct EchoProvider {
+        url: Option<String>,
+    }
+
+    #[async_trait]
+    impl TextToImageProvider for EchoProvider {
+        async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            if let Some(url) = &self.url {
+                instrumentation::get(url).await?.error_for_status()?;
+            }
+            let prompt = request.prompt.positive_prompt.unwrap_or_default();
+            Ok(vec![LvmImage {
+                data: prompt.into_bytes(),
+                metadata: None,
+            }])
+        }
+
+        fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+            Some(1)
+        }
+    }
+
+    /// Rewrites prompts, adds a header, and rejects prompts containing "forbidden".
+    struct Rewrite;
+
+    #[async_trait]
+    impl Middleware for Rewrite {
+        async fn before_request(&self, context: &mut RequestContext) -> Result<()> {
+            let prompt = context
+                .request
+                .prompt
+                .positive_prompt
+                .get_or_insert_default();
+            if prompt.contains("forbidden") {
+                return Err(anyhow!("Prompt rejected."));
+            }
+            prompt.push_str(", watercolor");
+            context
+                .headers
+                .insert("x-tenant", HeaderValue::from_static("acme"));
+            Ok(())
+        }
+    }
+
+    /// Appends a suffix to every image.
+    struct Suffix(&'static str);
+
+    #[async_trait]
+    impl Middleware for Suffix {
+        async fn after_response(
+            &self,
+            _context: &RequestContext,
+            images: &mut Vec<LvmImage>,
+        ) -> Result<()> {
+            for image in images {
+                image.data.extend_from_slice(self.0.as_bytes());
+            }
+            Ok(())
+        }
+    }
+
+    fn request(prompt: &str, num_batches: u32) -> TextToImageRequest {
+        TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some(prompt.to_string()),
+                negative_prompt: None,
+            },
+            num_batches: Some(num_batches),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware() -> Result<()> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("x-tenant", "acme"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let provider = LvmProviders::Custom(CustomProvider::new(EchoProvider {
+            url: Some(server.uri()),
+        }))
+        .with_middleware(Rewrite)
+        .with_middleware(Suffix("!"))
+        .with_middleware(Suffix("?"));
+        let images = provider.text_to_image(request("a cat", 2)).await?;
+        assert_eq!(images.len(), 2);
+        // Images go back through the middleware in reverse order.
+        assert_eq!(images[0].data, b"a cat, watercolor?!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_veto() {
+        let provider =
+            MiddlewareProvider::new(LvmProviders::Custom(CustomProvider::new(EchoProvider {
+                url: None,
+            })))
+            .with(Rewrite);
+        let error = provider
+            .text_to_image(request("something forbidden", 1))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Prompt rejected.");
+    }
+}
@@ -1,6 +1,40 @@
 pub mod automatic1111;
+mod batch;
+pub mod cache;
+#[cfg(feature = "comfyui")]
+pub mod comfyui;
+pub mod custom;
+pub mod fallback;
+pub mod hedged;
+#[cfg(feature = "huggingface")]
+pub mod huggingface;
+#[cfg(feature = "imagen")]
+pub mod imagen;
 mod index;
+#[cfg(feature = "invokeai")]
+pub mod invokeai;
+pub mod middleware;
 pub mod openai;
+#[cfg(feature = "openai-compatible")]
+pub mod openai_compatible;
+pub mod pricing;
+#[cfg(feature = "replicate")]
+pub mod replicate;
+pub mod shared;
+#[cfg(feature = "stability")]
+pub mod stability;
+pub mod sweep;
+pub mod usage;
+#[cfg(feature = "xai")]
 pub mod xai;
 
+pub use batch::BatchResults;
 pub use index::LvmProviders;
+
+/// Generate a random seed, e.g. for providers that need one to be set.
+pub(crate) fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
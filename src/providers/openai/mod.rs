@@ -2,13 +2,20 @@ use std::ops::Deref;
 
 use crate::{
     images::LvmImage,
-    parameters::{provider::ProviderConfiguration, text_to_image::TextToImageRequest},
+    instrumentation::warning,
+    parameters::{
+        provider::ProviderConfiguration,
+        text_to_image::{OpenAiImageQuality, OpenAiImageStyle, TextToImageRequest},
+    },
     traits::TextToImageProvider,
 };
 use anyhow::Result;
 use async_openai::{
     Client,
-    types::{CreateImageRequestArgs, ImageModel, ImageResponseFormat, ImageSize},
+    types::{
+        CreateImageRequest, CreateImageRequestArgs, ImageModel, ImageQuality, ImageResponseFormat,
+        ImageSize, ImageStyle,
+    },
 };
 use async_trait::async_trait;
 use dotenvy::dotenv;
@@ -44,12 +51,71 @@ fn to_openai_batch_size(num_batches: Option<u32>) -> u8 {
         if num_batches > 0 && num_batches < 256 {
             num_batches as u8
         } else {
-            eprintln!("Invalid number of batches. Using default value of 1.");
+            warning!("Invalid number of batches. Using default value of 1.");
             1
         }
     })
 }
 
+impl From<OpenAiImageQuality> for ImageQuality {
+    fn from(quality: OpenAiImageQuality) -> Self {
+        match quality {
+            OpenAiImageQuality::Standard => ImageQuality::Standard,
+            OpenAiImageQuality::Hd => ImageQuality::HD,
+        }
+    }
+}
+
+impl From<OpenAiImageStyle> for ImageStyle {
+    fn from(style: OpenAiImageStyle) -> Self {
+        match style {
+            OpenAiImageStyle::Vivid => ImageStyle::Vivid,
+            OpenAiImageStyle::Natural => ImageStyle::Natural,
+        }
+    }
+}
+
+/// Convert a request into an OpenAI request.
+fn to_openai_request(request: TextToImageRequest) -> Result<CreateImageRequest> {
+    let mut args = CreateImageRequestArgs::default();
+    args.model(to_openai_model(request.model))
+        .prompt(request.prompt.positive_prompt.unwrap_or(" ".to_string()))
+        .n(to_openai_batch_size(request.num_batches))
+        .response_format(ImageResponseFormat::B64Json)
+        .size(to_openai_size(request.width, request.height));
+    if let Some(openai) = request.openai {
+        if let Some(quality) = openai.quality {
+            args.quality(quality);
+        }
+        if let Some(style) = openai.style {
+            args.style(style);
+        }
+        if let Some(user) = openai.user {
+            args.user(user);
+        }
+    }
+    Ok(args.build()?)
+}
+
+/// Pick the size closest to the requested size that the model supports.
+pub fn resolve_size(
+    model: Option<String>,
+    size: &crate::parameters::size::ImageSize,
+) -> Result<(u32, u32)> {
+    match to_openai_model(model) {
+        ImageModel::DallE3 => size.resolve_from(&[(1024, 1024), (1792, 1024), (1024, 1792)]),
+        _ => size.resolve_from(&[(256, 256), (512, 512), (1024, 1024)]),
+    }
+}
+
+/// The maximum number of images OpenAI can generate in a single request to the given model.
+pub fn max_images_per_request(model: Option<String>) -> u32 {
+    match to_openai_model(model) {
+        ImageModel::DallE3 => 1,
+        _ => 10,
+    }
+}
+
 #[async_trait]
 impl TextToImageProvider for OpenAiProvider {
     async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
@@ -64,13 +130,7 @@ impl TextToImageProvider for OpenAiProvider {
         let client = Client::new();
 
         // Create the request.
-        let request = CreateImageRequestArgs::default()
-            .model(to_openai_model(request.model))
-            .prompt(request.prompt.positive_prompt.unwrap_or(" ".to_string()))
-            .n(to_openai_batch_size(request.num_batches))
-            .response_format(ImageResponseFormat::B64Json)
-            .size(to_openai_size(request.width, request.height))
-            .build()?;
+        let request = to_openai_request(request)?;
 
         // Send the request to OpenAI's API.
         let response = client.images().create(request).await?;
@@ -81,6 +141,18 @@ impl TextToImageProvider for OpenAiProvider {
             .map(|image| image.deref().clone().into())
             .collect())
     }
+
+    fn max_images_per_request(&self, request: &TextToImageRequest) -> Option<u32> {
+        Some(max_images_per_request(request.model.clone()))
+    }
+
+    fn resolve_size(
+        &self,
+        request: &TextToImageRequest,
+        size: &crate::parameters::size::ImageSize,
+    ) -> Result<(u32, u32)> {
+        resolve_size(request.model.clone(), size)
+    }
 }
 
 impl From<&ProviderConfiguration> for OpenAiProvider {
@@ -92,6 +164,7 @@ impl From<&ProviderConfiguration> for OpenAiProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parameters::text_to_image::OpenAiRequestParameters;
 
     #[test]
     fn test_to_openai_size() {
@@ -110,4 +183,44 @@ mod tests {
         assert_eq!(to_openai_size(Some(256), Some(256)), ImageSize::S256x256);
         assert_eq!(to_openai_size(Some(512), Some(512)), ImageSize::S512x512);
     }
+
+    #[test]
+    fn test_to_openai_request() -> Result<()> {
+        let request = TextToImageRequest {
+            model: Some("dall-e-3".to_string()),
+            openai: Some(OpenAiRequestParameters {
+                quality: Some(OpenAiImageQuality::Hd),
+                style: Some(OpenAiImageStyle::Natural),
+                user: Some("user-1234".to_string()),
+            }),
+            ..Default::default()
+        };
+        let request = to_openai_request(request)?;
+        assert_eq!(request.n, Some(1));
+        assert_eq!(request.quality, Some(ImageQuality::HD));
+        assert_eq!(request.style, Some(ImageStyle::Natural));
+        assert_eq!(request.user, Some("user-1234".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_size() -> Result<()> {
+        let size = crate::parameters::size::ImageSize::Closest {
+            width: 600,
+            height: 500,
+        };
+        assert_eq!(
+            resolve_size(Some("dall-e-3".to_string()), &size)?,
+            (1024, 1024)
+        );
+        assert_eq!(resolve_size(None, &size)?, (512, 512));
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_images_per_request() {
+        assert_eq!(max_images_per_request(Some("dall-e-3".to_string())), 1);
+        assert_eq!(max_images_per_request(Some("dall-e-2".to_string())), 10);
+        assert_eq!(max_images_per_request(None), 10);
+    }
 }
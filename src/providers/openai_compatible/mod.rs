@@ -0,0 +1,386 @@
+//! A provider for any endpoint implementing OpenAI's images API, e.g. xAI, LocalAI or an internal gateway.
+
+use crate::{
+    errors::{ContentRejectedError, ProviderConfigurationError},
+    images::LvmImage,
+    instrumentation::SendTraced,
+    parameters::{
+        provider::ProviderConfiguration, size::ImageSize, text_to_image::TextToImageRequest,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// How the API key is sent to the endpoint.
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    /// Send the key as `Authorization: Bearer <key>`, like OpenAI.
+    #[default]
+    Bearer,
+    /// Send the key as-is in the given header, e.g. `api-key` for Azure OpenAI.
+    Header(String),
+    /// Don't send a key.
+    None,
+}
+
+/// Optional fields of an OpenAI images API request.
+/// The prompt and model are always sent.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenAiRequestField {
+    N,
+    Size,
+    ResponseFormat,
+    Quality,
+    Style,
+    User,
+}
+
+impl OpenAiRequestField {
+    /// The name of the field in the request body.
+    fn key(&self) -> &'static str {
+        match self {
+            OpenAiRequestField::N => "n",
+            OpenAiRequestField::Size => "size",
+            OpenAiRequestField::ResponseFormat => "response_format",
+            OpenAiRequestField::Quality => "quality",
+            OpenAiRequestField::Style => "style",
+            OpenAiRequestField::User => "user",
+        }
+    }
+}
+
+/// Configuration for an endpoint implementing OpenAI's images API.
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct OpenAiCompatibleConfiguration {
+    /// The base URL is required, e.g. `http://localhost:8080/v1`.
+    /// `api_key_env_var` is required unless `auth` is `None`.
+    #[serde(flatten)]
+    pub provider: ProviderConfiguration,
+    /// How the API key is sent to the endpoint.
+    #[serde(default)]
+    pub auth: AuthScheme,
+    /// The model to use if the request doesn't set one.
+    pub default_model: Option<String>,
+    /// The optional fields the endpoint accepts. All fields are sent if this is `None`.
+    pub allowed_fields: Option<Vec<OpenAiRequestField>>,
+    /// The maximum number of images the endpoint can generate in a single request.
+    /// Requests are never split up if this is `None`.
+    pub max_images_per_request: Option<u32>,
+    /// The sizes the endpoint can generate. Any size is sent as-is if this is `None`.
+    pub sizes: Option<Vec<(u32, u32)>>,
+}
+
+impl OpenAiCompatibleConfiguration {
+    /// Whether an optional field may be sent to the endpoint.
+    fn allows(&self, field: OpenAiRequestField) -> bool {
+        self.allowed_fields
+            .as_ref()
+            .is_none_or(|fields| fields.contains(&field))
+    }
+
+    /// Pick the size closest to the requested size that the endpoint supports.
+    pub fn resolve_size(&self, size: &ImageSize) -> Result<(u32, u32)> {
+        match &self.sizes {
+            Some(sizes) => size.resolve_from(sizes),
+            // Without a list of sizes, assume the endpoint runs a Stable Diffusion-like model.
+            None => size.resolve_to_multiple(8, 1024 * 1024),
+        }
+    }
+
+    fn configuration_error(&self, message: &str) -> anyhow::Error {
+        anyhow!(ProviderConfigurationError {
+            message: message.to_string(),
+            configuration: self.provider.clone(),
+        })
+    }
+}
+
+/// A provider for an endpoint implementing OpenAI's images API.
+#[derive(Debug, Clone)]
+pub struct OpenAiCompatibleProvider {
+    pub configuration: OpenAiCompatibleConfiguration,
+}
+
+impl From<&OpenAiCompatibleConfiguration> for OpenAiCompatibleProvider {
+    fn from(configuration: &OpenAiCompatibleConfiguration) -> Self {
+        OpenAiCompatibleProvider {
+            configuration: configuration.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ImagesResponse {
+    data: Vec<async_openai::types::Image>,
+    /// Reported by models billed by token, e.g. gpt-image-1.
+    usage: Option<ImagesUsage>,
+}
+
+#[derive(Deserialize)]
+struct ImagesUsage {
+    total_tokens: u32,
+}
+
+/// Build the body of an image generation request, leaving out the fields the endpoint doesn't accept.
+fn to_request_body(
+    configuration: &OpenAiCompatibleConfiguration,
+    request: TextToImageRequest,
+) -> Value {
+    let mut body = Map::new();
+    body.insert(
+        "prompt".to_string(),
+        request.prompt.positive_prompt.unwrap_or_default().into(),
+    );
+    if let Some(model) = request.model.or(configuration.default_model.clone()) {
+        body.insert("model".to_string(), model.into());
+    }
+
+    let mut optional = Map::new();
+    optional.insert("n".to_string(), request.num_batches.unwrap_or(1).into());
+    optional.insert("response_format".to_string(), "b64_json".into());
+    if let (Some(width), Some(height)) = (request.width, request.height) {
+        optional.insert("size".to_string(), format!("{}x{}", width, height).into());
+    }
+    if let Some(openai) = request.openai {
+        if let Some(quality) = openai.quality {
+            optional.insert("quality".to_string(), serde_json::json!(quality));
+        }
+        if let Some(style) = openai.style {
+            optional.insert("style".to_string(), serde_json::json!(style));
+        }
+        if let Some(user) = openai.user {
+            optional.insert("user".to_string(), user.into());
+        }
+    }
+    for field in [
+        OpenAiRequestField::N,
+        OpenAiRequestField::Size,
+        OpenAiRequestField::ResponseFormat,
+        OpenAiRequestField::Quality,
+        OpenAiRequestField::Style,
+        OpenAiRequestField::User,
+    ] {
+        if configuration.allows(field)
+            && let Some(value) = optional.remove(field.key())
+        {
+            body.insert(field.key().to_string(), value);
+        }
+    }
+    Value::Object(body)
+}
+
+#[async_trait]
+impl TextToImageProvider for OpenAiCompatibleProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        let configuration = &self.configuration;
+        let base_url = configuration
+            .provider
+            .base_url
+            .as_ref()
+            .ok_or_else(|| configuration.configuration_error("base_url is required"))?;
+
+        let mut http_request = reqwest::Client::new()
+            .post(format!(
+                "{}/images/generations",
+                base_url.trim_end_matches('/')
+            ))
+            .json(&to_request_body(configuration, request));
+
+        if configuration.auth != AuthScheme::None {
+            // Load environment variables from a .env file, if there is one.
+            dotenv().ok();
+            let api_key_env_var = configuration
+                .provider
+                .api_key_env_var
+                .as_ref()
+                .ok_or_else(|| configuration.configuration_error("api_key_env_var is required"))?;
+            let api_key = std::env::var(api_key_env_var).map_err(|_| {
+                configuration.configuration_error(&format!(
+                    "{} environment variable not set",
+                    api_key_env_var
+                ))
+            })?;
+            http_request = match &configuration.auth {
+                AuthScheme::Header(header) => http_request.header(header, api_key),
+                _ => http_request.bearer_auth(api_key),
+            };
+        }
+
+        let response = http_request.send_traced().await?;
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let status = response.status();
+            let response = response.text().await?;
+            let message = format!(
+                "Failed to generate image. Base URL: {:?}, Status: {}, Response: {:?}",
+                base_url, status, response
+            );
+            // OpenAI reports prompts rejected by its safety system with the `content_policy_violation` code.
+            if serde_json::from_str::<Value>(&response)
+                .is_ok_and(|body| body["error"]["code"] == "content_policy_violation")
+            {
+                return Err(anyhow!(ContentRejectedError { message }));
+            }
+            return Err(anyhow!(message));
+        }
+
+        let response: ImagesResponse = response.json().await?;
+        let mut images: Vec<LvmImage> = response
+            .data
+            .into_iter()
+            .map(|image| image.into())
+            .collect();
+        if let (Some(usage), Some(image)) = (response.usage, images.first_mut()) {
+            image.metadata_mut().tokens = Some(usage.total_tokens);
+        }
+        Ok(images)
+    }
+
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        self.configuration.max_images_per_request
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        self.configuration.resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        prompt::ImagePrompt,
+        text_to_image::{OpenAiImageQuality, OpenAiRequestParameters},
+    };
+    use serde_json::json;
+    use serial_test::serial;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_json, header, method, path},
+    };
+
+    const TEST_API_KEY_ENV_VAR: &str = "LVM_MULTI_API_TEST_OPENAI_COMPATIBLE_API_KEY";
+
+    fn request() -> TextToImageRequest {
+        TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: None,
+            },
+            width: Some(512),
+            height: Some(512),
+            num_batches: Some(2),
+            openai: Some(OpenAiRequestParameters {
+                quality: Some(OpenAiImageQuality::Hd),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_to_request_body() {
+        let configuration = OpenAiCompatibleConfiguration {
+            default_model: Some("stablediffusion".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            to_request_body(&configuration, request()),
+            json!({
+                "prompt": "A painting of a cat",
+                "model": "stablediffusion",
+                "n": 2,
+                "size": "512x512",
+                "response_format": "b64_json",
+                "quality": "hd",
+            })
+        );
+    }
+
+    #[tokio::test]
+    #[serial(openai_compatible_api_key)]
+    async fn test_text_to_image() -> Result<()> {
+        // SAFETY: Tests that touch this variable are run serially.
+        unsafe { std::env::set_var(TEST_API_KEY_ENV_VAR, "test-key") };
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/images/generations"))
+            .and(header("api-key", "test-key"))
+            .and(body_json(json!({
+                "prompt": "A painting of a cat",
+                "model": "internal-model",
+                "n": 2,
+                "response_format": "b64_json",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "created": 0,
+                "data": [{ "b64_json": "aW1hZ2U=" }, { "b64_json": "aW1hZ2U=" }],
+                "usage": { "input_tokens": 10, "output_tokens": 2080, "total_tokens": 2090 },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = OpenAiCompatibleProvider::from(&OpenAiCompatibleConfiguration {
+            provider: ProviderConfiguration {
+                base_url: Some(format!("{}/v1", server.uri())),
+                api_key_env_var: Some(TEST_API_KEY_ENV_VAR.to_string()),
+                ..Default::default()
+            },
+            auth: AuthScheme::Header("api-key".to_string()),
+            default_model: Some("internal-model".to_string()),
+            allowed_fields: Some(vec![
+                OpenAiRequestField::N,
+                OpenAiRequestField::ResponseFormat,
+            ]),
+            ..Default::default()
+        });
+        let images = provider.text_to_image(request()).await?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].decode()?, b"image");
+        assert_eq!(
+            images[0].metadata.as_ref().and_then(|m| m.tokens),
+            Some(2090)
+        );
+        assert_eq!(images[1].metadata.as_ref().and_then(|m| m.tokens), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_content_policy_violation() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "error": { "code": "content_policy_violation", "message": "Rejected by the safety system." },
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = OpenAiCompatibleProvider::from(&OpenAiCompatibleConfiguration {
+            provider: ProviderConfiguration {
+                base_url: Some(server.uri()),
+                ..Default::default()
+            },
+            auth: AuthScheme::None,
+            ..Default::default()
+        });
+        let error = provider.text_to_image(request()).await.unwrap_err();
+        assert_eq!(
+            crate::ErrorKind::of(&error),
+            crate::ErrorKind::ContentRejected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_text_to_image_missing_base_url() {
+        let provider = OpenAiCompatibleProvider::from(&OpenAiCompatibleConfiguration::default());
+        let error = provider.text_to_image(request()).await.unwrap_err();
+        assert!(error.downcast_ref::<ProviderConfigurationError>().is_some());
+    }
+}
@@ -0,0 +1,312 @@
+//! Estimate what requests cost before sending them.
+
+use crate::{
+    parameters::text_to_image::{OpenAiImageQuality, TextToImageRequest},
+    providers::index::LvmProviders,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// The price of an image generated by a provider, in US dollars.
+/// Prices apply to the requests they match. Fields that are `None` match any request.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Price {
+    /// The provider's name, as returned by [`LvmProviders::name`].
+    pub provider: String,
+    /// The model, compared ignoring case.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// The size the image is generated at.
+    #[serde(default)]
+    pub size: Option<(u32, u32)>,
+    /// The quality requested from OpenAI. Requests without a quality match `standard`.
+    #[serde(default)]
+    pub quality: Option<OpenAiImageQuality>,
+    pub per_image: f64,
+}
+
+impl Price {
+    fn new(provider: &str, model: Option<&str>, per_image: f64) -> Self {
+        Price {
+            provider: provider.to_string(),
+            model: model.map(str::to_string),
+            size: None,
+            quality: None,
+            per_image,
+        }
+    }
+
+    fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    fn with_quality(mut self, quality: OpenAiImageQuality) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    /// How many of the price's fields are set, so more specific prices are preferred.
+    fn specificity(&self) -> usize {
+        [
+            self.model.is_some(),
+            self.size.is_some(),
+            self.quality.is_some(),
+        ]
+        .into_iter()
+        .filter(|set| *set)
+        .count()
+    }
+}
+
+/// The prices of the images generated by each provider.
+/// The default table has the published prices of the hosted providers, and local providers are free.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct PricingTable {
+    pub prices: Vec<Price>,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        use OpenAiImageQuality::{Hd, Standard};
+        PricingTable {
+            prices: vec![
+                // Requests without a model are sent to DALL·E 2, and requests without a size are generated at 1024x1024.
+                Price::new("open-ai", None, 0.02),
+                Price::new("open-ai", None, 0.018).with_size(512, 512),
+                Price::new("open-ai", None, 0.016).with_size(256, 256),
+                Price::new("open-ai", Some("dall-e-3"), 0.04),
+                Price::new("open-ai", Some("dall-e-3"), 0.08).with_size(1792, 1024),
+                Price::new("open-ai", Some("dall-e-3"), 0.08).with_size(1024, 1792),
+                Price::new("open-ai", Some("dall-e-3"), 0.08).with_quality(Hd),
+                Price::new("open-ai", Some("dall-e-3"), 0.12)
+                    .with_size(1792, 1024)
+                    .with_quality(Hd),
+                Price::new("open-ai", Some("dall-e-3"), 0.12)
+                    .with_size(1024, 1792)
+                    .with_quality(Hd),
+                // gpt-image-1 is billed by token. These are the prices of medium quality images for standard quality,
+                // and of high quality images for HD quality.
+                Price::new("open-ai-compatible", Some("gpt-image-1"), 0.042).with_quality(Standard),
+                Price::new("open-ai-compatible", Some("gpt-image-1"), 0.063)
+                    .with_size(1536, 1024)
+                    .with_quality(Standard),
+                Price::new("open-ai-compatible", Some("gpt-image-1"), 0.063)
+                    .with_size(1024, 1536)
+                    .with_quality(Standard),
+                Price::new("open-ai-compatible", Some("gpt-image-1"), 0.167).with_quality(Hd),
+                Price::new("open-ai-compatible", Some("gpt-image-1"), 0.25)
+                    .with_size(1536, 1024)
+                    .with_quality(Hd),
+                Price::new("open-ai-compatible", Some("gpt-image-1"), 0.25)
+                    .with_size(1024, 1536)
+                    .with_quality(Hd),
+                Price::new("x-ai", None, 0.07),
+                Price::new("imagen", None, 0.04),
+                // Requests without a model are sent to Stable Image Core.
+                Price::new("stability", None, 0.03),
+                Price::new("stability", Some("ultra"), 0.08),
+                Price::new("stability", Some("sd3.5-large"), 0.065),
+                Price::new("stability", Some("sd3.5-large-turbo"), 0.04),
+                Price::new("stability", Some("sd3.5-medium"), 0.035),
+                // Other models on Replicate and Hugging Face are billed by compute time, so they aren't priced.
+                Price::new("replicate", Some("black-forest-labs/flux-schnell"), 0.003),
+                Price::new("replicate", Some("black-forest-labs/flux-dev"), 0.025),
+                Price::new("replicate", Some("black-forest-labs/flux-1.1-pro"), 0.04),
+                Price::new(
+                    "replicate",
+                    Some("black-forest-labs/flux-1.1-pro-ultra"),
+                    0.06,
+                ),
+                Price::new(
+                    "replicate",
+                    Some("stability-ai/stable-diffusion-3.5-large"),
+                    0.065,
+                ),
+                Price::new(
+                    "hugging-face",
+                    Some("black-forest-labs/FLUX.1-schnell"),
+                    0.003,
+                ),
+                Price::new("hugging-face", Some("black-forest-labs/FLUX.1-dev"), 0.025),
+                Price::new("automatic1111", None, 0.0),
+                Price::new("automatic1111-pool", None, 0.0),
+                Price::new("comfy-ui", None, 0.0),
+                Price::new("invoke-ai", None, 0.0),
+            ],
+        }
+    }
+}
+
+impl PricingTable {
+    /// The price of a single image, or `None` if no price matches.
+    pub fn price(
+        &self,
+        provider: &str,
+        model: Option<&str>,
+        size: Option<(u32, u32)>,
+        quality: Option<OpenAiImageQuality>,
+    ) -> Option<f64> {
+        let quality = quality.unwrap_or_default();
+        self.prices
+            .iter()
+            .filter(|price| {
+                price.provider == provider
+                    && price.model.as_deref().is_none_or(|price_model| {
+                        model.is_some_and(|model| model.eq_ignore_ascii_case(price_model))
+                    })
+                    && price.size.is_none_or(|price_size| size == Some(price_size))
+                    && price
+                        .quality
+                        .is_none_or(|price_quality| price_quality == quality)
+            })
+            // Ties go to the price listed first.
+            .rev()
+            .max_by_key(|price| price.specificity())
+            .map(|price| price.per_image)
+    }
+
+    /// The price of a single image generated for a request, or `None` if no price matches.
+    pub fn price_per_image(
+        &self,
+        provider: &LvmProviders,
+        request: &TextToImageRequest,
+    ) -> Result<Option<f64>> {
+        Ok(self.price(
+            provider.name(),
+            request.model.as_deref(),
+            provider.resolve_size(request)?,
+            request.openai.as_ref().and_then(|openai| openai.quality),
+        ))
+    }
+
+    /// Estimate what a request costs, or `None` if no price matches.
+    /// Providers that wrap others are estimated at the most they could cost, e.g. the most expensive step of a fallback
+    /// chain, both providers of a hedged request and no cache hits. They are `None` if any provider they wrap isn't priced.
+    pub fn estimate(
+        &self,
+        provider: &LvmProviders,
+        request: &TextToImageRequest,
+    ) -> Result<Option<f64>> {
+        match provider {
+            LvmProviders::Fallback(fallback) => {
+                let mut most = Some(0.0_f64);
+                for (index, step) in fallback.steps.iter().enumerate() {
+                    let estimate =
+                        self.estimate(&step.provider, &fallback.step_request(index, request))?;
+                    most = most
+                        .zip(estimate)
+                        .map(|(most, estimate)| most.max(estimate));
+                }
+                Ok(most)
+            }
+            LvmProviders::Hedged(hedged) => Ok(self
+                .estimate(&hedged.primary, request)?
+                .zip(self.estimate(&hedged.secondary, request)?)
+                .map(|(primary, secondary)| primary + secondary)),
+            LvmProviders::Cached(cached) => self.estimate(&cached.provider, request),
+            LvmProviders::Metered(metered) => self.estimate(&metered.provider, request),
+            LvmProviders::Middleware(middleware) => self.estimate(&middleware.provider, request),
+            _ => Ok(self
+                .price_per_image(provider, request)?
+                .map(|price| price * f64::from(num_images(request)))),
+        }
+    }
+}
+
+/// The number of images a request generates, which is `batch_size` images for each of its batches.
+pub(crate) fn num_images(request: &TextToImageRequest) -> u32 {
+    let batch_size = request
+        .extended
+        .as_ref()
+        .and_then(|extended| extended.batch_size)
+        .unwrap_or(1)
+        .max(1);
+    request.num_batches.unwrap_or(1).max(1) * batch_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        provider::ProviderConfiguration, text_to_image::OpenAiRequestParameters,
+    };
+
+    #[test]
+    fn test_price() {
+        let table = PricingTable::default();
+        assert_eq!(table.price("open-ai", None, None, None), Some(0.02));
+        assert_eq!(
+            table.price("open-ai", Some("DALL-E-3"), Some((1792, 1024)), None),
+            Some(0.08)
+        );
+        assert_eq!(
+            table.price(
+                "open-ai",
+                Some("dall-e-3"),
+                Some((1024, 1792)),
+                Some(OpenAiImageQuality::Hd)
+            ),
+            Some(0.12)
+        );
+        assert_eq!(table.price("stability", None, None, None), Some(0.03));
+        assert_eq!(
+            table.price("replicate", Some("owner/model"), None, None),
+            None
+        );
+    }
+
+    #[cfg(all(
+        feature = "openai",
+        feature = "automatic1111",
+        feature = "openai-compatible"
+    ))]
+    #[test]
+    fn test_estimate_fallback() -> Result<()> {
+        use crate::{
+            parameters::text_to_image::TextToImageRequestExtendedParameters,
+            providers::fallback::FallbackProvider,
+        };
+        let table = PricingTable::default();
+        let request = TextToImageRequest {
+            extended: Some(TextToImageRequestExtendedParameters {
+                batch_size: Some(2),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let fallback = LvmProviders::Fallback(FallbackProvider::new(vec![
+            LvmProviders::Automatic1111(ProviderConfiguration::default()),
+            LvmProviders::OpenAi(ProviderConfiguration::default()),
+        ]));
+        let estimate = table.estimate(&fallback, &request)?.unwrap_or_default();
+        assert!((estimate - 0.04).abs() < 1e-9);
+
+        let unpriced = LvmProviders::Fallback(FallbackProvider::new(vec![
+            LvmProviders::OpenAi(ProviderConfiguration::default()),
+            LvmProviders::OpenAiCompatible(Default::default()),
+        ]));
+        assert_eq!(table.estimate(&unpriced, &request)?, None);
+        Ok(())
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_estimate() -> Result<()> {
+        let provider = LvmProviders::OpenAi(ProviderConfiguration::default());
+        let request = TextToImageRequest {
+            model: Some("dall-e-3".to_string()),
+            width: Some(1792),
+            height: Some(1024),
+            num_batches: Some(3),
+            openai: Some(OpenAiRequestParameters {
+                quality: Some(OpenAiImageQuality::Hd),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let estimate = provider.estimate_cost(&request)?.unwrap_or_default();
+        assert!((estimate - 0.36).abs() < 1e-9);
+        Ok(())
+    }
+}
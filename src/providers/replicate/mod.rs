@@ -0,0 +1,507 @@
+//! A provider for prediction-style APIs like Replicate's.
+//!
+//! A prediction is created with the model's inputs, then fetched from its `urls.get` URL until it has finished.
+//! The `output` of a finished prediction is the URL of an image, or a list of them.
+
+use crate::{
+    errors::{ContentRejectedError, ProviderConfigurationError},
+    images::{LvmImage, LvmImageMetadata},
+    instrumentation::{self, SendTraced, event, in_span},
+    parameters::{
+        field::RequestField, provider::ProviderConfiguration, size::ImageSize,
+        text_to_image::TextToImageRequest,
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use base64::Engine;
+use dotenvy::dotenv;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+const DEFAULT_BASE_URL: &str = "https://api.replicate.com/v1";
+const DEFAULT_API_KEY_ENV_VAR: &str = "REPLICATE_API_TOKEN";
+
+/// How long to wait for a prediction to finish.
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Configuration for a model hosted on a prediction API.
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct ReplicateConfiguration {
+    #[serde(flatten)]
+    pub provider: ProviderConfiguration,
+    /// The ID of the model version to run.
+    /// If this is `None`, the request's model is run as an official model, e.g. `black-forest-labs/flux-schnell`.
+    pub version: Option<String>,
+    /// Which field of the request each input of the model is set from.
+    /// If this is `None`, the inputs used by most Stable Diffusion models are set.
+    pub input_mapping: Option<BTreeMap<String, RequestField>>,
+    /// Inputs sent with every prediction, e.g. `{"output_format": "png"}`.
+    #[serde(default)]
+    pub input: Map<String, Value>,
+    /// Wait up to this many seconds for the prediction to finish when creating it, using the `Prefer: wait` header.
+    /// Predictions that are still running after that are polled.
+    pub wait: Option<u32>,
+    /// A URL the API sends the prediction to when it changes.
+    pub webhook: Option<String>,
+    /// Which events are sent to the webhook, e.g. `start` or `completed`.
+    pub webhook_events_filter: Option<Vec<String>>,
+}
+
+impl ReplicateConfiguration {
+    /// The input mapping, or the default mapping if none is set.
+    fn input_mapping(&self) -> BTreeMap<String, RequestField> {
+        self.input_mapping.clone().unwrap_or_else(|| {
+            BTreeMap::from([
+                ("prompt".to_string(), RequestField::PositivePrompt),
+                ("negative_prompt".to_string(), RequestField::NegativePrompt),
+                ("width".to_string(), RequestField::Width),
+                ("height".to_string(), RequestField::Height),
+                ("num_outputs".to_string(), RequestField::NumBatches),
+                ("num_inference_steps".to_string(), RequestField::Steps),
+                ("guidance_scale".to_string(), RequestField::CfgScale),
+                ("scheduler".to_string(), RequestField::SamplerName),
+                ("seed".to_string(), RequestField::Seed),
+            ])
+        })
+    }
+
+    /// The maximum number of images the model can generate in a single prediction.
+    /// Models can only generate several images if the number of batches is mapped to one of their inputs.
+    pub fn max_images_per_request(&self) -> Option<u32> {
+        if self
+            .input_mapping()
+            .values()
+            .any(|field| *field == RequestField::NumBatches)
+        {
+            None
+        } else {
+            Some(1)
+        }
+    }
+}
+
+/// A provider for a model hosted on a prediction API.
+#[derive(Debug, Clone)]
+pub struct ReplicateProvider {
+    pub configuration: ReplicateConfiguration,
+}
+
+impl From<&ReplicateConfiguration> for ReplicateProvider {
+    fn from(configuration: &ReplicateConfiguration) -> Self {
+        ReplicateProvider {
+            configuration: configuration.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Prediction {
+    id: String,
+    /// One of `starting`, `processing`, `succeeded`, `failed` or `canceled`.
+    status: String,
+    #[serde(default)]
+    output: Value,
+    #[serde(default)]
+    error: Value,
+    urls: PredictionUrls,
+}
+
+#[derive(Debug, Deserialize)]
+struct PredictionUrls {
+    get: String,
+}
+
+impl Prediction {
+    fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "succeeded" | "failed" | "canceled")
+    }
+
+    /// Whether the prediction failed because the model's safety checker flagged the image.
+    fn is_content_rejected(&self) -> bool {
+        self.error
+            .as_str()
+            .is_some_and(|error| error.to_lowercase().contains("nsfw"))
+    }
+
+    /// The URLs of the images in the output.
+    fn output_urls(&self) -> Vec<&str> {
+        match &self.output {
+            Value::String(url) => vec![url.as_str()],
+            Value::Array(urls) => urls.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Build the body of a create prediction request.
+fn to_prediction_body(
+    configuration: &ReplicateConfiguration,
+    request: &TextToImageRequest,
+) -> Value {
+    let mut input = configuration.input.clone();
+    for (name, field) in configuration.input_mapping() {
+        if let Some(value) = field.value(request) {
+            input.insert(name, value);
+        }
+    }
+    let mut body = json!({ "input": input });
+    if let Some(version) = &configuration.version {
+        body["version"] = version.clone().into();
+    }
+    if let Some(webhook) = &configuration.webhook {
+        body["webhook"] = webhook.clone().into();
+    }
+    if let Some(events) = &configuration.webhook_events_filter {
+        body["webhook_events_filter"] = events.clone().into();
+    }
+    body
+}
+
+impl ReplicateProvider {
+    fn base_url(&self) -> &str {
+        self.configuration
+            .provider
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+            .trim_end_matches('/')
+    }
+
+    fn api_key(&self) -> Result<String> {
+        // Load environment variables from a .env file, if there is one.
+        dotenv().ok();
+        let api_key_env_var = self
+            .configuration
+            .provider
+            .api_key_env_var
+            .as_deref()
+            .unwrap_or(DEFAULT_API_KEY_ENV_VAR);
+        std::env::var(api_key_env_var).map_err(|_| {
+            anyhow!(ProviderConfigurationError {
+                message: format!("{} environment variable not set", api_key_env_var),
+                configuration: self.configuration.provider.clone(),
+            })
+        })
+    }
+
+    /// Send a POST request to create a prediction.
+    /// Versioned models are run through `/predictions`, and official models through `/models/{model}/predictions`.
+    async fn create_prediction(
+        &self,
+        api_key: &str,
+        request: &TextToImageRequest,
+    ) -> Result<Prediction> {
+        let url = match (&self.configuration.version, &request.model) {
+            (None, Some(model)) => format!("{}/models/{}/predictions", self.base_url(), model),
+            (None, None) => {
+                return Err(anyhow!(ProviderConfigurationError {
+                    message: "Either a version or a model is required".to_string(),
+                    configuration: self.configuration.provider.clone(),
+                }));
+            }
+            _ => format!("{}/predictions", self.base_url()),
+        };
+        let mut http_request = reqwest::Client::new()
+            .post(&url)
+            .bearer_auth(api_key)
+            .json(&to_prediction_body(&self.configuration, request));
+        if let Some(wait) = self.configuration.wait {
+            http_request = http_request.header("Prefer", format!("wait={}", wait));
+        }
+
+        let response = http_request.send_traced().await?;
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let status = response.status();
+            let response = response.text().await?;
+            return Err(anyhow!(
+                "Failed to create prediction. Request URL: {:?}, Status: {}, Response: {:?}",
+                &url,
+                status,
+                response
+            ));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Poll a prediction's `urls.get` URL until it has finished.
+    async fn poll_prediction(&self, api_key: &str, prediction: Prediction) -> Result<Prediction> {
+        let mut prediction = prediction;
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        while !prediction.is_finished() {
+            interval.tick().await;
+            let polled = in_span!(
+                async {
+                    let polled: Prediction = reqwest::Client::new()
+                        .get(&prediction.urls.get)
+                        .bearer_auth(api_key)
+                        .send_traced()
+                        .await?
+                        .error_for_status()?
+                        .json()
+                        .await?;
+                    event!(debug, status = %polled.status, "Polled prediction");
+                    anyhow::Ok(polled)
+                },
+                tracing::debug_span!("poll_prediction", prediction_id = %prediction.id)
+            )
+            .await?;
+            prediction = polled;
+        }
+        Ok(prediction)
+    }
+}
+
+/// Download an output image, or decode it if the API returned it as a data URI.
+async fn download_output(url: &str) -> Result<Vec<u8>> {
+    if let Some(data) = url.strip_prefix("data:") {
+        let (_, data) = data
+            .split_once(";base64,")
+            .ok_or_else(|| anyhow!("Output is not a base64 data URI."))?;
+        return Ok(base64::prelude::BASE64_STANDARD.decode(data)?);
+    }
+    let response = instrumentation::get(url).await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+#[async_trait]
+impl TextToImageProvider for ReplicateProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        let api_key = self.api_key()?;
+        let prediction = self.create_prediction(&api_key, &request).await?;
+        let prediction = tokio::time::timeout(TIMEOUT, self.poll_prediction(&api_key, prediction))
+            .await
+            .map_err(|_| anyhow!("Prediction timed out."))??;
+
+        if prediction.status != "succeeded" {
+            let message = format!(
+                "Prediction {} did not succeed. Status: {}, Error: {}",
+                prediction.id, prediction.status, prediction.error
+            );
+            if prediction.is_content_rejected() {
+                return Err(anyhow!(ContentRejectedError { message }));
+            }
+            return Err(anyhow!(message));
+        }
+
+        let generation_params = Some(format!("Prediction: {}", prediction.id));
+        let mut images = Vec::new();
+        for url in prediction.output_urls() {
+            let image = download_output(url).await?;
+            images.push(LvmImage {
+                data: base64::prelude::BASE64_STANDARD
+                    .encode(image)
+                    .as_bytes()
+                    .to_vec(),
+                metadata: Some(LvmImageMetadata {
+                    generation_params: generation_params.clone(),
+                    ..Default::default()
+                }),
+            });
+        }
+        Ok(images)
+    }
+
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        self.configuration.max_images_per_request()
+    }
+
+    // Most hosted image models accept any size that is a multiple of 8.
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        crate::providers::automatic1111::resolve_size(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        prompt::ImagePrompt, text_to_image::TextToImageRequestExtendedParameters,
+    };
+    use serial_test::serial;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_json, header, method, path},
+    };
+
+    const TEST_API_KEY_ENV_VAR: &str = "LVM_MULTI_API_TEST_REPLICATE_API_TOKEN";
+
+    fn set_test_api_key() {
+        // SAFETY: Tests that touch this variable are run serially.
+        unsafe { std::env::set_var(TEST_API_KEY_ENV_VAR, "test-token") };
+    }
+
+    fn configuration(server: &MockServer) -> ReplicateConfiguration {
+        ReplicateConfiguration {
+            provider: ProviderConfiguration {
+                base_url: Some(server.uri()),
+                api_key_env_var: Some(TEST_API_KEY_ENV_VAR.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn request() -> TextToImageRequest {
+        TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: None,
+            },
+            num_batches: Some(2),
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed: Some(42),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_to_prediction_body() {
+        let configuration = ReplicateConfiguration {
+            version: Some("abcd".to_string()),
+            input_mapping: Some(BTreeMap::from([(
+                "text".to_string(),
+                RequestField::PositivePrompt,
+            )])),
+            input: Map::from_iter([("output_format".to_string(), json!("png"))]),
+            webhook: Some("https://example.com/webhook".to_string()),
+            webhook_events_filter: Some(vec!["completed".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            to_prediction_body(&configuration, &request()),
+            json!({
+                "version": "abcd",
+                "input": { "text": "A painting of a cat", "output_format": "png" },
+                "webhook": "https://example.com/webhook",
+                "webhook_events_filter": ["completed"],
+            })
+        );
+        assert_eq!(configuration.max_images_per_request(), Some(1));
+        assert_eq!(
+            ReplicateConfiguration::default().max_images_per_request(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    #[serial(replicate_api_key)]
+    async fn test_text_to_image_polling() -> Result<()> {
+        set_test_api_key();
+        let server = MockServer::start().await;
+        let get_url = format!("{}/predictions/1234", server.uri());
+        Mock::given(method("POST"))
+            .and(path("/predictions"))
+            .and(header("authorization", "Bearer test-token"))
+            .and(body_json(json!({
+                "version": "abcd",
+                "input": { "prompt": "A painting of a cat", "num_outputs": 2, "seed": 42 },
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": "1234",
+                "status": "starting",
+                "output": null,
+                "urls": { "get": get_url },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/predictions/1234"))
+            .and(header("authorization", "Bearer test-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "1234",
+                "status": "succeeded",
+                "output": [
+                    format!("{}/files/first.png", server.uri()),
+                    format!("{}/files/second.png", server.uri()),
+                ],
+                "urls": { "get": get_url },
+            })))
+            .mount(&server)
+            .await;
+        for name in ["first.png", "second.png"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/files/{}", name)))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(name.as_bytes()))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let provider = ReplicateProvider::from(&ReplicateConfiguration {
+            version: Some("abcd".to_string()),
+            ..configuration(&server)
+        });
+        let images = provider.text_to_image(request()).await?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].decode()?, b"first.png");
+        assert_eq!(images[1].decode()?, b"second.png");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial(replicate_api_key)]
+    async fn test_text_to_image_wait() -> Result<()> {
+        set_test_api_key();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/models/black-forest-labs/flux-schnell/predictions"))
+            .and(header("prefer", "wait=60"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": "1234",
+                "status": "succeeded",
+                "output": "data:image/png;base64,aW1hZ2U=",
+                "urls": { "get": format!("{}/predictions/1234", server.uri()) },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = ReplicateProvider::from(&ReplicateConfiguration {
+            wait: Some(60),
+            ..configuration(&server)
+        });
+        let request = TextToImageRequest {
+            model: Some("black-forest-labs/flux-schnell".to_string()),
+            ..request()
+        };
+        let images = provider.text_to_image(request).await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].decode()?, b"image");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial(replicate_api_key)]
+    async fn test_text_to_image_failed() {
+        set_test_api_key();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/predictions"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": "1234",
+                "status": "failed",
+                "error": "NSFW content detected",
+                "urls": { "get": format!("{}/predictions/1234", server.uri()) },
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = ReplicateProvider::from(&ReplicateConfiguration {
+            version: Some("abcd".to_string()),
+            ..configuration(&server)
+        });
+        let error = provider.text_to_image(request()).await.unwrap_err();
+        assert!(error.to_string().contains("NSFW content detected"));
+        assert_eq!(
+            crate::ErrorKind::of(&error),
+            crate::ErrorKind::ContentRejected
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Providers that are built once and reused by every request sent through them.
+
+use crate::{
+    providers::{custom::CustomProvider, index::LvmProviders},
+    traits::TextToImageProvider,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+
+/// A provider that is built the first time it is used, then reused by every request sent through it and its clones.
+/// Created with [`LvmProviders::shared`].
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SharedProvider {
+    /// The provider that is built, which is still reported as the provider's name and configuration.
+    provider: Box<LvmProviders>,
+    /// The built provider, with the configuration it was built with.
+    #[serde(skip)]
+    built: Arc<OnceLock<CustomProvider>>,
+}
+
+impl SharedProvider {
+    pub(crate) fn new(provider: LvmProviders) -> Self {
+        SharedProvider {
+            provider: Box::new(provider),
+            built: Arc::default(),
+        }
+    }
+
+    /// The provider that is built.
+    pub fn provider(&self) -> &LvmProviders {
+        &self.provider
+    }
+
+    /// The built provider, building it if this is the first time it is used.
+    pub fn build(&self) -> Result<Arc<dyn TextToImageProvider>> {
+        Ok(self.build_configured()?.provider)
+    }
+
+    /// The built provider along with its configuration, building it if this is the first time it is used.
+    pub(crate) fn build_configured(&self) -> Result<CustomProvider> {
+        if let Some(built) = self.built.get() {
+            return Ok(built.clone());
+        }
+        let built = self.provider.build_configured()?;
+        // If another task built it in the meantime, theirs is kept so there is only ever one instance.
+        Ok(self.built.get_or_init(|| built).clone())
+    }
+}
+
+impl std::fmt::Debug for SharedProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedProvider")
+            .field("provider", &self.provider)
+            .field("built", &self.built.get().is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for SharedProvider {
+    /// Shared providers are equal if they build equal providers, whether or not they have been built yet.
+    fn eq(&self, other: &Self) -> bool {
+        self.provider == other.provider
+    }
+}
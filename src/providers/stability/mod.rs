@@ -0,0 +1,341 @@
+use crate::{
+    errors::{ContentRejectedError, ProviderConfigurationError},
+    images::{LvmImage, LvmImageMetadata},
+    instrumentation::SendTraced,
+    parameters::{
+        provider::ProviderConfiguration,
+        size::ImageSize,
+        text_to_image::{StabilityOutputFormat, TextToImageRequest},
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use reqwest::multipart::Form;
+use serde::Deserialize;
+
+const DEFAULT_BASE_URL: &str = "https://api.stability.ai";
+const DEFAULT_API_KEY_ENV_VAR: &str = "STABILITY_API_KEY";
+const DEFAULT_MODEL: &str = "core";
+
+/// Stability AI only accepts these aspect ratios, listed with the size SD3 generates for each of them.
+const ASPECT_RATIOS: [(&str, (u32, u32)); 9] = [
+    ("1:1", (1024, 1024)),
+    ("16:9", (1344, 768)),
+    ("21:9", (1536, 640)),
+    ("2:3", (832, 1216)),
+    ("3:2", (1216, 832)),
+    ("4:5", (896, 1088)),
+    ("5:4", (1088, 896)),
+    ("9:16", (768, 1344)),
+    ("9:21", (640, 1536)),
+];
+
+/// A provider for generating images with Stability AI's v2beta Stable Image API.
+#[derive(Debug, Clone)]
+pub struct StabilityProvider {
+    pub base_url: String,
+    /// The name of the environment variable containing the API key.
+    pub api_key_env_var: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateResponse {
+    /// The image in base64 encoding.
+    image: String,
+    finish_reason: String,
+    seed: Option<u64>,
+}
+
+/// Pick the supported size closest to the requested size.
+pub fn resolve_size(size: &ImageSize) -> Result<(u32, u32)> {
+    let sizes: Vec<(u32, u32)> = ASPECT_RATIOS.iter().map(|(_, size)| *size).collect();
+    size.resolve_from(&sizes)
+}
+
+/// Find the aspect ratio parameter for an image size.
+fn to_stability_aspect_ratio(width: Option<u32>, height: Option<u32>) -> Option<&'static str> {
+    let (width, height) = (width?, height?);
+    let size = ImageSize::AspectRatio { width, height };
+    let resolved = resolve_size(&size).ok()?;
+    ASPECT_RATIOS
+        .iter()
+        .find(|(_, size)| *size == resolved)
+        .map(|(aspect_ratio, _)| *aspect_ratio)
+}
+
+/// Split a model name into the endpoint to call and the `model` parameter, if the endpoint needs one.
+/// `core` and `ultra` have their own endpoints, while all SD3 models share the `sd3` endpoint.
+fn to_stability_endpoint(model: Option<String>) -> (String, Option<String>) {
+    let model = model.unwrap_or(DEFAULT_MODEL.to_string());
+    if model.to_ascii_lowercase().starts_with("sd3") {
+        ("sd3".to_string(), Some(model))
+    } else {
+        (model.to_ascii_lowercase(), None)
+    }
+}
+
+fn to_stability_output_format(format: StabilityOutputFormat) -> &'static str {
+    match format {
+        StabilityOutputFormat::Png => "png",
+        StabilityOutputFormat::Jpeg => "jpeg",
+        StabilityOutputFormat::Webp => "webp",
+    }
+}
+
+/// Convert a request into the endpoint to call and the multipart form to send to it.
+fn to_stability_form(request: TextToImageRequest) -> (String, Form) {
+    let (endpoint, model) = to_stability_endpoint(request.model);
+    let mut form = Form::new().text("prompt", request.prompt.positive_prompt.unwrap_or_default());
+    if let Some(model) = model {
+        form = form.text("model", model);
+    }
+    if let Some(negative_prompt) = request.prompt.negative_prompt {
+        form = form.text("negative_prompt", negative_prompt);
+    }
+    if let Some(aspect_ratio) = to_stability_aspect_ratio(request.width, request.height) {
+        form = form.text("aspect_ratio", aspect_ratio);
+    }
+    if let Some(seed) = request.extended.and_then(|extended| extended.seed) {
+        form = form.text("seed", seed.to_string());
+    }
+    if let Some(stability) = request.stability {
+        if let Some(output_format) = stability.output_format {
+            form = form.text("output_format", to_stability_output_format(output_format));
+        }
+        if let Some(style_preset) = stability.style_preset {
+            form = form.text("style_preset", style_preset);
+        }
+    }
+    (endpoint, form)
+}
+
+#[async_trait]
+impl TextToImageProvider for StabilityProvider {
+    /// Generate an image using Stability AI. Each request generates a single image.
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        // Load environment variables from a .env file, if there is one.
+        dotenv().ok();
+
+        let api_key = std::env::var(&self.api_key_env_var).map_err(|_| {
+            anyhow!(ProviderConfigurationError {
+                message: format!("{} environment variable not set", self.api_key_env_var),
+                configuration: ProviderConfiguration {
+                    base_url: Some(self.base_url.clone()),
+                    api_key_env_var: Some(self.api_key_env_var.clone()),
+                    ..Default::default()
+                },
+            })
+        })?;
+
+        let (endpoint, form) = to_stability_form(request);
+        let url = format!(
+            "{}/v2beta/stable-image/generate/{}",
+            self.base_url, endpoint
+        );
+        let response = reqwest::Client::new()
+            .post(&url)
+            .bearer_auth(api_key)
+            .header("accept", "application/json")
+            .multipart(form)
+            .send_traced()
+            .await?;
+
+        // If the response is not successful, return an error.
+        if !response.status().is_success() {
+            let status = response.status();
+            let response = response.text().await?;
+            let message = format!(
+                "Failed to generate image. Request URL: {:?}, Status: {}, Response: {:?}",
+                &url, status, response
+            );
+            // Stability responds with 403 Forbidden when the request is flagged by its content moderation.
+            if status == reqwest::StatusCode::FORBIDDEN {
+                return Err(anyhow!(ContentRejectedError { message }));
+            }
+            return Err(anyhow!(message));
+        }
+
+        let response: GenerateResponse = response.json().await?;
+        if response.finish_reason == "CONTENT_FILTERED" {
+            return Err(anyhow!(ContentRejectedError {
+                message: "The image was filtered by Stability's content moderation.".to_string(),
+            }));
+        }
+        if response.finish_reason != "SUCCESS" {
+            return Err(anyhow!(
+                "Image generation did not succeed. Finish reason: {}",
+                response.finish_reason
+            ));
+        }
+        Ok(vec![LvmImage {
+            data: response.image.into_bytes(),
+            metadata: Some(LvmImageMetadata {
+                generation_params: response.seed.map(|seed| format!("Seed: {}", seed)),
+                ..Default::default()
+            }),
+        }])
+    }
+
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        Some(1)
+    }
+
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        resolve_size(size)
+    }
+}
+
+impl From<&ProviderConfiguration> for StabilityProvider {
+    fn from(config: &ProviderConfiguration) -> Self {
+        StabilityProvider {
+            base_url: config
+                .base_url
+                .clone()
+                .unwrap_or(DEFAULT_BASE_URL.to_string()),
+            api_key_env_var: config
+                .api_key_env_var
+                .clone()
+                .unwrap_or(DEFAULT_API_KEY_ENV_VAR.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ErrorKind;
+    use crate::parameters::{
+        prompt::ImagePrompt,
+        text_to_image::{StabilityRequestParameters, TextToImageRequestExtendedParameters},
+    };
+    use serial_test::serial;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{body_string_contains, header, method, path},
+    };
+
+    const TEST_API_KEY_ENV_VAR: &str = "LVM_MULTI_API_TEST_STABILITY_API_KEY";
+
+    fn set_test_api_key() {
+        // SAFETY: Tests that touch this variable are run serially.
+        unsafe { std::env::set_var(TEST_API_KEY_ENV_VAR, "test-key") };
+    }
+
+    #[test]
+    fn test_to_stability_endpoint() {
+        assert_eq!(to_stability_endpoint(None), ("core".to_string(), None));
+        assert_eq!(
+            to_stability_endpoint(Some("Ultra".to_string())),
+            ("ultra".to_string(), None)
+        );
+        assert_eq!(
+            to_stability_endpoint(Some("sd3.5-large".to_string())),
+            ("sd3".to_string(), Some("sd3.5-large".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_to_stability_aspect_ratio() {
+        assert_eq!(
+            to_stability_aspect_ratio(Some(1920), Some(1080)),
+            Some("16:9")
+        );
+        assert_eq!(to_stability_aspect_ratio(Some(512), Some(512)), Some("1:1"));
+        assert_eq!(to_stability_aspect_ratio(None, Some(512)), None);
+    }
+
+    #[tokio::test]
+    #[serial(stability_api_key)]
+    async fn test_text_to_image() -> Result<()> {
+        set_test_api_key();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2beta/stable-image/generate/sd3"))
+            .and(header("authorization", "Bearer test-key"))
+            .and(body_string_contains("sd3.5-large"))
+            .and(body_string_contains("A painting of a cat"))
+            .and(body_string_contains("dog"))
+            .and(body_string_contains("16:9"))
+            .and(body_string_contains("1234"))
+            .and(body_string_contains("jpeg"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "image": "aW1hZ2U=",
+                "finish_reason": "SUCCESS",
+                "seed": 1234,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let provider = StabilityProvider {
+            base_url: server.uri(),
+            api_key_env_var: TEST_API_KEY_ENV_VAR.to_string(),
+        };
+        let request = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("A painting of a cat".to_string()),
+                negative_prompt: Some("dog".to_string()),
+            },
+            model: Some("sd3.5-large".to_string()),
+            width: Some(1344),
+            height: Some(768),
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed: Some(1234),
+                ..Default::default()
+            }),
+            stability: Some(StabilityRequestParameters {
+                output_format: Some(StabilityOutputFormat::Jpeg),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let images = provider.text_to_image(request).await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].decode()?, b"image");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial(stability_api_key)]
+    async fn test_text_to_image_content_filtered() {
+        set_test_api_key();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2beta/stable-image/generate/core"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "image": "",
+                "finish_reason": "CONTENT_FILTERED",
+                "seed": 1,
+            })))
+            .mount(&server)
+            .await;
+
+        let provider = StabilityProvider {
+            base_url: server.uri(),
+            api_key_env_var: TEST_API_KEY_ENV_VAR.to_string(),
+        };
+        let error = provider
+            .text_to_image(TextToImageRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::ContentRejected);
+    }
+
+    #[tokio::test]
+    #[serial(stability_api_key)]
+    async fn test_text_to_image_missing_api_key() {
+        let provider = StabilityProvider {
+            base_url: "http://localhost".to_string(),
+            api_key_env_var: "LVM_MULTI_API_TEST_UNSET_API_KEY".to_string(),
+        };
+        let result = provider.text_to_image(TextToImageRequest::default()).await;
+        assert!(
+            result
+                .unwrap_err()
+                .downcast_ref::<ProviderConfigurationError>()
+                .is_some()
+        );
+    }
+}
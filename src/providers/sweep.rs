@@ -0,0 +1,348 @@
+//! Compare parameters by generating an image for every combination of their values, like Automatic1111's X/Y/Z plot.
+
+use crate::{
+    images::LvmImage,
+    parameters::{field::RequestField, text_to_image::TextToImageRequest},
+    providers::{batch::BatchResults, index::LvmProviders},
+};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A parameter that a sweep changes, with the values it is set to.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepAxis {
+    /// Set a field of the request to each value, e.g. `cfg_scale` to `5.0`, `7.0` and `9.0`.
+    Field {
+        field: RequestField,
+        values: Vec<Value>,
+    },
+    /// Replace the first value in the prompts with each value in turn, like Automatic1111's Prompt S/R.
+    /// The first value is kept as it is.
+    PromptReplace { values: Vec<String> },
+}
+
+impl SweepAxis {
+    pub fn field<V: Into<Value>>(field: RequestField, values: impl IntoIterator<Item = V>) -> Self {
+        SweepAxis::Field {
+            field,
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn prompt_replace<S: Into<String>>(values: impl IntoIterator<Item = S>) -> Self {
+        SweepAxis::PromptReplace {
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// The number of values on the axis.
+    pub fn len(&self) -> usize {
+        match self {
+            SweepAxis::Field { values, .. } => values.len(),
+            SweepAxis::PromptReplace { values } => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The label of a value, e.g. `cfg_scale: 7.0`, or `None` if the axis has no value at `index`.
+    pub fn label(&self, index: usize) -> Option<String> {
+        match self {
+            SweepAxis::Field { field, values } => {
+                // Strings are shown without the quotes they have as JSON.
+                let value = match values.get(index)? {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                Some(format!("{}: {}", field, value))
+            }
+            SweepAxis::PromptReplace { values } => values.get(index).cloned(),
+        }
+    }
+
+    /// Change a request to use a value.
+    fn apply(&self, index: usize, request: &mut TextToImageRequest) -> Result<()> {
+        let out_of_range = || anyhow!("The sweep axis has no value at index {}.", index);
+        match self {
+            SweepAxis::Field { field, values } => {
+                field.set_value(request, values.get(index).ok_or_else(out_of_range)?)
+            }
+            SweepAxis::PromptReplace { values } => {
+                let search = values
+                    .first()
+                    .ok_or_else(|| anyhow!("The sweep axis has no values."))?;
+                let replace = values.get(index).ok_or_else(out_of_range)?;
+                let prompts = [
+                    &mut request.prompt.positive_prompt,
+                    &mut request.prompt.negative_prompt,
+                ];
+                if !prompts.iter().any(|prompt| {
+                    prompt
+                        .as_deref()
+                        .is_some_and(|p| p.contains(search.as_str()))
+                }) {
+                    return Err(anyhow!("The prompt doesn't contain {:?}.", search));
+                }
+                for prompt in prompts.into_iter().flatten() {
+                    *prompt = prompt.replace(search.as_str(), replace);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A request sent for each combination of the values of up to three axes.
+/// Set a seed on the base request so that the images only differ by the parameters being compared.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Sweep {
+    pub base: TextToImageRequest,
+    /// The values compared across the columns of a grid.
+    pub x: SweepAxis,
+    /// The values compared across the rows of a grid.
+    #[serde(default)]
+    pub y: Option<SweepAxis>,
+    /// The values compared across separate grids.
+    #[serde(default)]
+    pub z: Option<SweepAxis>,
+}
+
+/// The position of a request in a sweep, as the index of its value on each axis.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SweepPosition {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+/// The results of a request sent by a sweep.
+#[derive(Debug)]
+pub struct SweepCell {
+    pub position: SweepPosition,
+    pub request: TextToImageRequest,
+    pub results: BatchResults,
+}
+
+/// The results of every request sent by a sweep.
+#[derive(Debug)]
+pub struct SweepResults {
+    pub sweep: Sweep,
+    /// The cells in order of `z`, then `y`, then `x`.
+    pub cells: Vec<SweepCell>,
+}
+
+impl Sweep {
+    pub fn new(base: TextToImageRequest, x: SweepAxis) -> Self {
+        Sweep {
+            base,
+            x,
+            y: None,
+            z: None,
+        }
+    }
+
+    pub fn with_y(mut self, y: SweepAxis) -> Self {
+        self.y = Some(y);
+        self
+    }
+
+    pub fn with_z(mut self, z: SweepAxis) -> Self {
+        self.z = Some(z);
+        self
+    }
+
+    fn axes(&self) -> [Option<&SweepAxis>; 3] {
+        [Some(&self.x), self.y.as_ref(), self.z.as_ref()]
+    }
+
+    /// The request for every combination of values, in order of `z`, then `y`, then `x`.
+    pub fn requests(&self) -> Result<Vec<(SweepPosition, TextToImageRequest)>> {
+        if self.axes().into_iter().flatten().any(SweepAxis::is_empty) {
+            return Err(anyhow!("Sweep axes must have at least one value."));
+        }
+        let len = |axis: Option<&SweepAxis>| axis.map_or(1, SweepAxis::len);
+        let mut requests = Vec::new();
+        for z in 0..len(self.z.as_ref()) {
+            for y in 0..len(self.y.as_ref()) {
+                for x in 0..self.x.len() {
+                    let mut request = self.base.clone();
+                    let position = SweepPosition { x, y, z };
+                    for (axis, index) in self.axes().into_iter().zip([x, y, z]) {
+                        if let Some(axis) = axis {
+                            axis.apply(index, &mut request)?;
+                        }
+                    }
+                    requests.push((position, request));
+                }
+            }
+        }
+        Ok(requests)
+    }
+
+    /// Send every request to the provider, one after another so they don't compete for the same GPU.
+    pub async fn run(&self, provider: &LvmProviders) -> Result<SweepResults> {
+        let mut cells = Vec::new();
+        for (position, request) in self.requests()? {
+            let results = provider.text_to_image_batched(request.clone()).await;
+            cells.push(SweepCell {
+                position,
+                request,
+                results,
+            });
+        }
+        Ok(SweepResults {
+            sweep: self.clone(),
+            cells,
+        })
+    }
+}
+
+impl SweepResults {
+    /// All the images generated by the sweep.
+    pub fn images(&self) -> impl Iterator<Item = &LvmImage> {
+        self.cells.iter().flat_map(|cell| &cell.results.images)
+    }
+
+    /// Draw a grid for each value of `z`, with the first image of each request labelled by its `x` and `y` values.
+    /// Requests that failed are left empty.
+    #[cfg(feature = "image")]
+    pub fn grids(&self) -> Result<Vec<LvmImage>> {
+        use crate::images::grid::Grid;
+
+        let labels = |axis: Option<&SweepAxis>| {
+            axis.map(|axis| {
+                (0..axis.len())
+                    .filter_map(|index| axis.label(index))
+                    .collect()
+            })
+            .unwrap_or_default()
+        };
+        let z_len = self.sweep.z.as_ref().map_or(1, SweepAxis::len);
+        let mut grids = Vec::new();
+        for z in 0..z_len {
+            let cells = self
+                .cells
+                .iter()
+                .filter(|cell| cell.position.z == z)
+                .map(|cell| {
+                    cell.results
+                        .images
+                        .first()
+                        .map(LvmImage::to_rgba)
+                        .transpose()
+                })
+                .collect::<Result<_>>()?;
+            let grid = Grid {
+                cells,
+                columns: self.sweep.x.len(),
+                column_labels: labels(Some(&self.sweep.x)),
+                row_labels: labels(self.sweep.y.as_ref()),
+                title: self.sweep.z.as_ref().and_then(|axis| axis.label(z)),
+                ..Default::default()
+            };
+            grids.push(LvmImage::from_rgba(grid.render())?);
+        }
+        Ok(grids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        LvmImageMetadata,
+        parameters::{prompt::ImagePrompt, text_to_image::TextToImageRequestExtendedParameters},
+        providers::custom::CustomProvider,
+        traits::TextToImageProvider,
+    };
+    use async_trait::async_trait;
+
+    /// Generates a 1x1 PNG, recording the request it was sent as its generation parameters.
+    struct EchoProvider;
+
+    #[async_trait]
+    impl TextToImageProvider for EchoProvider {
+        async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            if request.prompt.positive_prompt.as_deref() == Some("a dog") {
+                return Err(anyhow!("No dogs."));
+            }
+            Ok(vec![LvmImage {
+                // A 1x1 transparent PNG.
+                data: b"iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==".to_vec(),
+                metadata: Some(LvmImageMetadata {
+                    generation_params: serde_json::to_string(&request).ok(),
+                    ..Default::default()
+                }),
+            }])
+        }
+    }
+
+    fn sweep() -> Sweep {
+        let base = TextToImageRequest {
+            prompt: ImagePrompt {
+                positive_prompt: Some("a cat".to_string()),
+                negative_prompt: None,
+            },
+            extended: Some(TextToImageRequestExtendedParameters {
+                seed: Some(42),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        Sweep::new(base, SweepAxis::field(RequestField::CfgScale, [5.0, 7.0]))
+            .with_y(SweepAxis::prompt_replace(["cat", "dog"]))
+            .with_z(SweepAxis::field(
+                RequestField::SamplerName,
+                ["Euler a", "DPM++ 2M"],
+            ))
+    }
+
+    #[test]
+    fn test_requests() -> Result<()> {
+        let requests = sweep().requests()?;
+        assert_eq!(requests.len(), 8);
+        let (position, request) = &requests[7];
+        assert_eq!(*position, SweepPosition { x: 1, y: 1, z: 1 });
+        assert_eq!(request.prompt.positive_prompt.as_deref(), Some("a dog"));
+        let extended = request.extended.as_ref().unwrap();
+        assert_eq!(extended.cfg_scale, Some(7.0));
+        assert_eq!(extended.sampler_name.as_deref(), Some("DPM++ 2M"));
+        assert_eq!(extended.seed, Some(42));
+        assert_eq!(
+            sweep().z.and_then(|z| z.label(1)),
+            Some("sampler_name: DPM++ 2M".to_string())
+        );
+        assert_eq!(sweep().x.label(2), None);
+        let mut request = TextToImageRequest::default();
+        assert!(sweep().x.apply(2, &mut request).is_err());
+
+        let missing = Sweep::new(
+            TextToImageRequest::default(),
+            SweepAxis::prompt_replace(["cat"]),
+        );
+        assert!(missing.requests().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run() -> Result<()> {
+        let provider = LvmProviders::Custom(CustomProvider::new(EchoProvider));
+        let results = sweep().run(&provider).await?;
+        assert_eq!(results.cells.len(), 8);
+        assert_eq!(results.images().count(), 4);
+        assert!(results.cells[2].results.images.is_empty());
+
+        #[cfg(feature = "image")]
+        {
+            let grids = results.grids()?;
+            assert_eq!(grids.len(), 2);
+            let grid = grids[0].to_rgba()?;
+            assert!(grid.width() > 2 && grid.height() > 2);
+        }
+        Ok(())
+    }
+}
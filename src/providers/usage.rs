@@ -0,0 +1,251 @@
+//! Count what is generated by a provider and what it costs.
+
+use crate::{
+    images::LvmImage,
+    parameters::text_to_image::TextToImageRequest,
+    providers::{
+        batch::BatchResults,
+        index::LvmProviders,
+        pricing::{PricingTable, num_images},
+    },
+    traits::TextToImageProvider,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+/// What has been generated by a provider so far.
+#[derive(Debug, Default, Serialize, PartialEq, Clone)]
+pub struct Usage {
+    /// The requests sent to the provider, including the ones that failed.
+    pub requests: u64,
+    pub images: u64,
+    /// The tokens used by models billed by token, e.g. gpt-image-1.
+    pub tokens: u64,
+    /// The estimated cost of the images generated, in US dollars.
+    /// Images that no price matches are counted as free when there is no budget.
+    pub estimated_spend: f64,
+}
+
+/// Wraps a provider, counting its usage and rejecting requests that would go over a budget.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeteredProvider {
+    pub provider: Box<LvmProviders>,
+    #[serde(default)]
+    pub pricing: PricingTable,
+    /// The most that can be spent through the provider, in US dollars.
+    /// Requests are rejected if their estimated cost would take the spend over the budget,
+    /// or if no price matches them, since they can't be checked against it.
+    #[serde(default)]
+    pub budget: Option<f64>,
+    /// Shared by clones, so requests sent through a shared provider are all counted.
+    #[serde(skip)]
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl PartialEq for MeteredProvider {
+    /// Metered providers are equal if they are configured the same, whatever their usage.
+    fn eq(&self, other: &Self) -> bool {
+        self.provider == other.provider
+            && self.pricing == other.pricing
+            && self.budget == other.budget
+    }
+}
+
+impl MeteredProvider {
+    pub fn new(provider: LvmProviders) -> Self {
+        MeteredProvider {
+            provider: Box::new(provider),
+            pricing: PricingTable::default(),
+            budget: None,
+            usage: Arc::default(),
+        }
+    }
+
+    pub fn with_pricing(mut self, pricing: PricingTable) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: f64) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// What has been generated through this provider and its clones so far.
+    pub fn usage(&self) -> Usage {
+        self.usage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Send the request to the provider if it fits in the budget, and count what it generates.
+    /// The estimated cost is reserved while the request is sent, so concurrent requests can't go over the budget together.
+    pub fn text_to_image_batched(
+        &self,
+        request: TextToImageRequest,
+    ) -> Pin<Box<dyn Future<Output = BatchResults> + Send + '_>> {
+        // Boxed, since the provider can itself be metered.
+        Box::pin(async move {
+            let num_images = num_images(&request);
+            let estimate = match self.pricing.estimate(&self.provider, &request) {
+                Ok(Some(estimate)) => estimate,
+                Ok(None) if self.budget.is_none() => 0.0,
+                Ok(None) => {
+                    return BatchResults {
+                        images: Vec::new(),
+                        errors: vec![anyhow!(
+                            "No price matches the request to {}, so it can't be checked against the budget. \
+                             Add a price for it to the pricing table.",
+                            self.provider.name()
+                        )],
+                    };
+                }
+                Err(error) => {
+                    return BatchResults {
+                        images: Vec::new(),
+                        errors: vec![error],
+                    };
+                }
+            };
+            {
+                let mut usage = self.usage.lock().unwrap_or_else(|p| p.into_inner());
+                if let Some(budget) = self.budget
+                    && usage.estimated_spend + estimate > budget
+                {
+                    return BatchResults {
+                        images: Vec::new(),
+                        errors: vec![anyhow!(
+                            "Request would exceed the budget of ${:.2}. Spent: ${:.2}, Estimated cost: ${:.2}",
+                            budget,
+                            usage.estimated_spend,
+                            estimate
+                        )],
+                    };
+                }
+                usage.estimated_spend += estimate;
+            }
+
+            let results = self.provider.text_to_image_batched(request).await;
+
+            let mut usage = self.usage.lock().unwrap_or_else(|p| p.into_inner());
+            usage.requests += 1;
+            usage.images += results.images.len() as u64;
+            usage.tokens += results
+                .images
+                .iter()
+                .filter_map(|image| image.metadata.as_ref()?.tokens)
+                .map(u64::from)
+                .sum::<u64>();
+            // Only the images that were generated are paid for, and no more than were estimated.
+            let generated = (results.images.len() as f64 / f64::from(num_images)).min(1.0);
+            usage.estimated_spend -= estimate * (1.0 - generated);
+            drop(usage);
+            results
+        })
+    }
+}
+
+#[async_trait]
+impl TextToImageProvider for MeteredProvider {
+    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+        self.text_to_image_batched(request).await.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        images::LvmImageMetadata,
+        providers::{custom::CustomProvider, pricing::Price},
+    };
+
+    /// Generates the requested number of images, each using 100 tokens.
+    struct TokenProvider;
+
+    #[async_trait]
+    impl TextToImageProvider for TokenProvider {
+        async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
+            Ok((0..request.num_batches.unwrap_or(1))
+                .map(|_| LvmImage {
+                    data: b"aW1hZ2U=".to_vec(),
+                    metadata: Some(LvmImageMetadata {
+                        tokens: Some(100),
+                        ..Default::default()
+                    }),
+                })
+                .collect())
+        }
+    }
+
+    fn metered() -> MeteredProvider {
+        MeteredProvider::new(LvmProviders::Custom(CustomProvider::new(TokenProvider))).with_pricing(
+            PricingTable {
+                prices: vec![Price {
+                    provider: "custom".to_string(),
+                    model: None,
+                    size: None,
+                    quality: None,
+                    per_image: 0.5,
+                }],
+            },
+        )
+    }
+
+    fn request(num_batches: u32) -> TextToImageRequest {
+        TextToImageRequest {
+            num_batches: Some(num_batches),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage() -> Result<()> {
+        let metered = metered();
+        let provider = LvmProviders::Metered(metered.clone());
+        provider.text_to_image(request(2)).await?;
+        provider.text_to_image(request(1)).await?;
+        assert_eq!(
+            metered.usage(),
+            Usage {
+                requests: 2,
+                images: 3,
+                tokens: 300,
+                estimated_spend: 1.5,
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_budget() -> Result<()> {
+        let metered = metered().with_budget(1.0);
+        metered.text_to_image(request(2)).await?;
+        let error = metered.text_to_image(request(1)).await.unwrap_err();
+        assert!(error.to_string().contains("budget"));
+        assert_eq!(metered.usage().images, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_budget_unpriced() -> Result<()> {
+        let metered = metered().with_pricing(PricingTable { prices: Vec::new() });
+        metered.text_to_image(request(1)).await?;
+        assert_eq!(metered.usage().estimated_spend, 0.0);
+
+        let error = metered
+            .with_budget(100.0)
+            .text_to_image(request(1))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("No price matches"));
+        Ok(())
+    }
+}
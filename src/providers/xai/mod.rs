@@ -1,94 +1,52 @@
-use crate::{
-    images::LvmImage,
-    parameters::{provider::ProviderConfiguration, text_to_image::TextToImageRequest},
-    traits::TextToImageProvider,
-};
-use anyhow::Result;
-use async_openai::{
-    Client,
-    config::OpenAIConfig,
-    types::{CreateImageRequestArgs, ImageModel, ImageResponseFormat},
+//! xAI's image generation API, which implements OpenAI's images API.
+
+use crate::parameters::provider::ProviderConfiguration;
+use crate::providers::openai_compatible::{
+    AuthScheme, OpenAiCompatibleConfiguration, OpenAiRequestField,
 };
-use async_trait::async_trait;
-use dotenvy::dotenv;
-use serde_json::json;
 
 const XAI_BASE_URL: &str = "https://api.x.ai/v1";
+const XAI_API_KEY_ENV_VAR: &str = "XAI_API_KEY";
+const XAI_DEFAULT_MODEL: &str = "grok-2-image";
 
-// There's nothing to configure on a provider level for OpenAI.
-pub struct XAiProvider {}
-
-#[derive(serde::Deserialize)]
-struct ImagesResponse {
-    data: Vec<async_openai::types::Image>,
-}
-
-fn to_xai_model(model: Option<String>) -> ImageModel {
-    model.map_or(ImageModel::Other("grok-2-image".to_string()), |model| {
-        ImageModel::Other(model)
-    })
-}
-
-fn to_xai_batch_size(num_batches: Option<u32>) -> u8 {
-    num_batches.map_or(1, |num_batches| {
-        if num_batches > 0 && num_batches < 256 {
-            num_batches as u8
-        } else {
-            eprintln!("Invalid number of batches. Using default value of 1.");
-            1
-        }
-    })
-}
+/// The maximum number of images xAI can generate in a single request.
+const MAX_IMAGES_PER_REQUEST: u32 = 10;
 
-#[async_trait]
-impl TextToImageProvider for XAiProvider {
-    async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>> {
-        // Load environment variables from a .env file.
-        dotenv()?;
-
-        // Get the API key from the environment.
-        // If the API key is not set, return an error telling the user to set it.
-        let api_key = std::env::var("XAI_API_KEY")
-            .map_err(|_| anyhow::anyhow!("XAI_API_KEY environment variable not set."))?;
-
-        // Create a new OpenAI client.
-        let client = Client::with_config(
-            OpenAIConfig::new()
-                .with_api_base(XAI_BASE_URL)
-                .with_api_key(api_key),
-        );
-
-        // Create the request.
-        let request = CreateImageRequestArgs::default()
-            .model(to_xai_model(request.model))
-            .prompt(request.prompt.positive_prompt.unwrap_or_default())
-            .n(to_xai_batch_size(request.num_batches))
-            .response_format(ImageResponseFormat::B64Json)
-            // The size parameter is not supported at the moment. Leave it empty.
-            //.size(to_xai_size(request.width, request.height))
-            .build()?;
-
-        // Send the request to OpenAI's API.
-        let response: ImagesResponse = client.images().create_byot(json!(request)).await?;
-
-        Ok(response
-            .data
-            .into_iter()
-            .map(|image| image.into())
-            .collect())
-    }
-}
+/// xAI doesn't accept a size and always generates images of this size.
+const XAI_IMAGE_SIZE: (u32, u32) = (1024, 768);
 
-impl From<&ProviderConfiguration> for XAiProvider {
-    fn from(_config: &ProviderConfiguration) -> Self {
-        XAiProvider {}
+/// The configuration of xAI as an OpenAI-compatible endpoint.
+/// The base URL and API key environment variable can be overridden.
+pub fn configuration(config: &ProviderConfiguration) -> OpenAiCompatibleConfiguration {
+    OpenAiCompatibleConfiguration {
+        provider: ProviderConfiguration {
+            base_url: config.base_url.clone().or(Some(XAI_BASE_URL.to_string())),
+            api_key_env_var: config
+                .api_key_env_var
+                .clone()
+                .or(Some(XAI_API_KEY_ENV_VAR.to_string())),
+            ..config.clone()
+        },
+        auth: AuthScheme::Bearer,
+        default_model: Some(XAI_DEFAULT_MODEL.to_string()),
+        // The size parameter is not supported at the moment.
+        allowed_fields: Some(vec![
+            OpenAiRequestField::N,
+            OpenAiRequestField::ResponseFormat,
+        ]),
+        max_images_per_request: Some(MAX_IMAGES_PER_REQUEST),
+        sizes: Some(vec![XAI_IMAGE_SIZE]),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{parameters::prompt::ImagePrompt, providers::LvmProviders};
+    use crate::{
+        images::LvmImage,
+        parameters::{prompt::ImagePrompt, text_to_image::TextToImageRequest},
+        providers::LvmProviders,
+    };
     use tokio::runtime::Runtime;
 
     /// Generate an image given a text input using XAI
@@ -1,10 +1,30 @@
-use crate::{images::LvmImage, parameters::text_to_image::TextToImageRequest};
+use crate::{
+    images::LvmImage,
+    parameters::{size::ImageSize, text_to_image::TextToImageRequest},
+};
 use anyhow::Result;
 use async_trait::async_trait;
 
+/// The number of pixels to aim for when only an aspect ratio is given and the provider doesn't resolve sizes itself.
+const DEFAULT_AREA: u32 = 1024 * 1024;
+
+/// A provider that generates images from text.
+/// Providers are shared between tasks, e.g. as `Arc<dyn TextToImageProvider>`.
 #[async_trait]
-pub trait TextToImageProvider {
+pub trait TextToImageProvider: Send + Sync {
     /// Generate an image given a text input using the provider's model.
     /// Other configuration should be set using the `self` object
     async fn text_to_image(&self, request: TextToImageRequest) -> Result<Vec<LvmImage>>;
+
+    /// The maximum number of images the provider can generate in a single call.
+    /// `None` if the provider handles any number of batches itself.
+    fn max_images_per_request(&self, _request: &TextToImageRequest) -> Option<u32> {
+        None
+    }
+
+    /// Pick the size closest to the requested size that the provider supports for a request.
+    /// By default any size is accepted.
+    fn resolve_size(&self, _request: &TextToImageRequest, size: &ImageSize) -> Result<(u32, u32)> {
+        size.resolve_to_multiple(1, DEFAULT_AREA)
+    }
 }